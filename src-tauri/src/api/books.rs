@@ -1,8 +1,13 @@
-use crate::http;
+use super::changes::{self, CatalogChanges};
+use super::detail_cache;
+use super::tag_index::{TagIndex, path_matches};
+use super::tags;
+use crate::downloader::sanitize_name;
 use crate::models::{CustomProperties, DataVersion, RawBook, Textbook};
+use crate::{http, storage};
 use futures_util::future::join_all;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -21,41 +26,162 @@ const DOWNLOAD_URL_SUFFIX: &str = ".pkg/pdf.pdf";
 // 统计接口用查询串传 id，分批防止 URL 过长
 const STATISTICS_BATCH_SIZE: usize = 50;
 
+// 持久化的书目（应用数据目录 cache/ 下），断网或重启后复用
+const CATALOG_CACHE_FILE: &str = "catalog.json";
+
 struct BooksCache {
     version: u64,
     books: Arc<Vec<RawBook>>,
+    index: Arc<TagIndex>,
+}
+
+impl BooksCache {
+    fn new(version: u64, books: Arc<Vec<RawBook>>) -> Self {
+        let index = Arc::new(TagIndex::build(&books));
+        Self {
+            version,
+            books,
+            index,
+        }
+    }
+
+    fn catalog(&self, offline: bool, changes: Option<Arc<CatalogChanges>>) -> Catalog {
+        Catalog {
+            module_version: self.version,
+            books: Arc::clone(&self.books),
+            index: Arc::clone(&self.index),
+            offline,
+            changes,
+        }
+    }
+}
+
+/// 全量书目。offline 表示网络不可用、用的是之前缓存的目录（可能不是最新）；
+/// changes 仅在本次调用发现了新版本且内容有变化时给出
+#[derive(Clone)]
+pub struct Catalog {
+    pub module_version: u64,
+    pub books: Arc<Vec<RawBook>>,
+    // tag id → 书目下标的倒排索引，筛选与计数用
+    pub index: Arc<TagIndex>,
+    pub offline: bool,
+    pub changes: Option<Arc<CatalogChanges>>,
+}
+
+#[derive(Serialize)]
+struct StoredCatalogRef<'a> {
+    module_version: u64,
+    books: &'a [RawBook],
+}
+
+#[derive(Deserialize)]
+struct StoredCatalog {
+    module_version: u64,
+    books: Vec<RawBook>,
 }
 
 // 全量书目有数 MB，按 module_version 缓存；tokio Mutex 同时把并发请求合并为一次下载
 static BOOKS_CACHE: Lazy<Mutex<Option<BooksCache>>> = Lazy::new(|| Mutex::new(None));
 
-pub async fn get_raw_books() -> Result<Arc<Vec<RawBook>>, String> {
-    // data_version 是小文件，每次都拉取，用于感知目录更新
-    let version: DataVersion = http::get_json(DATA_VERSION_URL).await?;
+pub async fn get_raw_books() -> Result<Catalog, String> {
+    // data_version 是小文件，每次都拉取，用于感知目录更新；拉不到即按离线处理
+    let version: DataVersion = match http::get_json(DATA_VERSION_URL).await {
+        Ok(version) => version,
+        Err(e) => return offline_catalog(e).await,
+    };
+
+    detail_cache::sync_version(version.module_version).await;
+    tags::sync_version(version.module_version);
 
     let mut cache = BOOKS_CACHE.lock().await;
     if let Some(cached) = cache.as_ref() {
         if cached.version == version.module_version {
-            return Ok(Arc::clone(&cached.books));
+            return Ok(cached.catalog(false, None));
         }
     }
 
-    log::info!(
-        "拉取全量书目 (版本 {}): {}",
-        version.module_version,
-        version.urls
-    );
-    let books = Arc::new(fetch_raw_books(&version).await?);
-    *cache = Some(BooksCache {
-        version: version.module_version,
-        books: Arc::clone(&books),
-    });
-    Ok(books)
+    // 上一版书目（内存里的优先，其次磁盘缓存），用于比对变化
+    let mut previous = cache
+        .as_ref()
+        .map(|cached| (cached.version, Arc::clone(&cached.books)));
+    // 本地缓存与线上版本一致时不必重新下载
+    let books = match storage::read_json::<StoredCatalog>(CATALOG_CACHE_FILE).await {
+        Some(stored) if stored.module_version == version.module_version => {
+            log::info!("使用本地缓存的书目 (版本 {})", stored.module_version);
+            Arc::new(stored.books)
+        }
+        stored => {
+            if previous.is_none() {
+                previous = stored.map(|s| (s.module_version, Arc::new(s.books)));
+            }
+            log::info!(
+                "拉取全量书目 (版本 {}): {}",
+                version.module_version,
+                version.urls
+            );
+            match fetch_raw_books(&version).await {
+                Ok(books) => {
+                    let books = Arc::new(books);
+                    tokio::spawn(persist_catalog(version.module_version, Arc::clone(&books)));
+                    books
+                }
+                Err(e) => {
+                    drop(cache);
+                    return offline_catalog(e).await;
+                }
+            }
+        }
+    };
+    let changes = match previous {
+        Some((old_version, old)) if old_version != version.module_version => {
+            changes::record(old_version, &old, version.module_version, &books).await
+        }
+        _ => None,
+    };
+    let loaded = BooksCache::new(version.module_version, books);
+    let catalog = loaded.catalog(false, changes);
+    *cache = Some(loaded);
+    Ok(catalog)
+}
+
+// 网络不可用：依次退回内存里的旧书目、磁盘缓存；都没有才报错
+async fn offline_catalog(error: String) -> Result<Catalog, String> {
+    let mut cache = BOOKS_CACHE.lock().await;
+    if cache.is_none() {
+        if let Some(stored) = storage::read_json::<StoredCatalog>(CATALOG_CACHE_FILE).await {
+            *cache = Some(BooksCache::new(stored.module_version, Arc::new(stored.books)));
+        }
+    }
+    let Some(cached) = cache.as_ref() else {
+        return Err(error);
+    };
+    log::warn!("书目获取失败，使用缓存的目录 (版本 {}): {error}", cached.version);
+    Ok(cached.catalog(true, None))
+}
+
+async fn persist_catalog(module_version: u64, books: Arc<Vec<RawBook>>) {
+    let stored = StoredCatalogRef {
+        module_version,
+        books: &books,
+    };
+    if let Err(e) = storage::write_json(CATALOG_CACHE_FILE, &stored).await {
+        log::warn!("保存书目缓存失败: {e}");
+    }
+}
+
+/// 内存里已加载的书目，不向服务端确认版本（尚未加载时为 None）
+pub async fn loaded_catalog() -> Option<Catalog> {
+    BOOKS_CACHE
+        .lock()
+        .await
+        .as_ref()
+        .map(|cached| cached.catalog(false, None))
 }
 
 pub async fn clear_cache() {
     *BOOKS_CACHE.lock().await = None;
-    DETAIL_CACHE.lock().await.clear();
+    detail_cache::clear().await;
+    storage::remove(CATALOG_CACHE_FILE).await;
 }
 
 #[derive(Debug, Deserialize)]
@@ -74,25 +200,18 @@ struct TiItem {
     ti_storages: Vec<String>,
 }
 
-// 资源详情缓存：下载与封面共用，避免重复请求
-static DETAIL_CACHE: Lazy<Mutex<HashMap<String, Arc<ResourceDetail>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-
-async fn get_detail(book_id: &str) -> Option<Arc<ResourceDetail>> {
-    if let Some(detail) = DETAIL_CACHE.lock().await.get(book_id) {
-        return Some(Arc::clone(detail));
-    }
+/// 教材详情接口地址
+pub fn detail_url(book_id: &str) -> String {
+    format!("{RESOURCE_DETAIL_URL_PREFIX}{book_id}.json")
+}
 
-    let url = format!("{RESOURCE_DETAIL_URL_PREFIX}{book_id}.json");
-    match http::get_json::<ResourceDetail>(&url).await {
-        Ok(detail) => {
-            let detail = Arc::new(detail);
-            DETAIL_CACHE
-                .lock()
-                .await
-                .insert(book_id.to_string(), Arc::clone(&detail));
-            Some(detail)
-        }
+// 资源详情走 detail_cache（有界 LRU + 磁盘），下载与封面共用，避免重复请求
+async fn get_detail(book_id: &str) -> Option<ResourceDetail> {
+    let detail = detail_cache::get(&detail_url(book_id))
+        .await
+        .and_then(|value| ResourceDetail::deserialize(value.as_ref()).map_err(|e| e.to_string()));
+    match detail {
+        Ok(detail) => Some(detail),
         Err(e) => {
             // thematic_course 等包装类型没有 tch_material 详情，属预期情况
             log::debug!("查询资源详情失败: {e}");
@@ -108,15 +227,9 @@ pub fn resource_id_from_url(url: &str) -> Option<&str> {
     (!id.is_empty()).then_some(id)
 }
 
-// 详情里声明的源 PDF 真实地址（多个 CDN 镜像）。
-// 部分教材（如盲校/特教）不走电子书处理管线，pkg 里没有 pdf.pdf 别名，
-// 只能按详情里声明的原始文件名下载；这也是官方阅读器的取法。
-pub async fn resolve_source_pdf_urls(resource_id: &str) -> Vec<String> {
-    let Some(detail) = get_detail(resource_id).await else {
-        return Vec::new();
-    };
-
-    let source = detail
+// 详情里声明的源 PDF：优先 source 标记的 pdf，没有时取任意 pdf
+fn source_pdf_item(detail: &ResourceDetail) -> Option<&TiItem> {
+    detail
         .ti_items
         .iter()
         .find(|ti| {
@@ -127,9 +240,60 @@ pub async fn resolve_source_pdf_urls(resource_id: &str) -> Vec<String> {
                 .ti_items
                 .iter()
                 .find(|ti| ti.ti_format.as_deref() == Some("pdf"))
-        });
+        })
+}
 
-    source.map(|ti| ti.ti_storages.clone()).unwrap_or_default()
+// 详情里声明的源 PDF 真实地址（多个 CDN 镜像）。
+// 部分教材（如盲校/特教）不走电子书处理管线，pkg 里没有 pdf.pdf 别名，
+// 只能按详情里声明的原始文件名下载；这也是官方阅读器的取法。
+pub async fn resolve_source_pdf_urls(resource_id: &str) -> Vec<String> {
+    let Some(detail) = get_detail(resource_id).await else {
+        return Vec::new();
+    };
+    source_pdf_item(&detail)
+        .map(|ti| ti.ti_storages.clone())
+        .unwrap_or_default()
+}
+
+/// 教材源文件的版本标识：源 PDF 地址，以及详情里各文件最新的转码时间戳
+/// （出版社重新发布、平台重新转码后变大）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceVersion {
+    pub source_url: String,
+    pub transcode_timestamp: u64,
+}
+
+impl SourceVersion {
+    /// 与下载时记录的版本相比是否有更新：转码更晚，或源文件换了地址
+    pub fn is_newer_than(&self, recorded: &SourceVersion) -> bool {
+        let path = |url: &str| url.split('?').next().unwrap_or_default().to_string();
+        self.transcode_timestamp > recorded.transcode_timestamp
+            || (!recorded.source_url.is_empty()
+                && !self.source_url.is_empty()
+                && path(&self.source_url) != path(&recorded.source_url))
+    }
+}
+
+fn detail_source_version(detail: &ResourceDetail) -> SourceVersion {
+    SourceVersion {
+        source_url: source_pdf_item(detail)
+            .and_then(|ti| ti.ti_storages.first())
+            .cloned()
+            .unwrap_or_default(),
+        transcode_timestamp: detail
+            .ti_items
+            .iter()
+            .flat_map(|ti| &ti.ti_storages)
+            .map(|url| transcode_timestamp(url))
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// 教材当前的源文件版本；详情不可用时为 None
+pub async fn source_version(book_id: &str) -> Option<SourceVersion> {
+    let detail = get_detail(book_id).await?;
+    Some(detail_source_version(&detail))
 }
 
 // 封面取详情里的 thumbnail_1：它与源 PDF 同批转码生成，保证列表封面与下载文件一致。
@@ -145,6 +309,14 @@ pub async fn resolve_cover_url(book_id: &str) -> Option<String> {
     Some(url.replace("-ndr-private.", "-ndr."))
 }
 
+/// 目录字段里选出的封面地址（详情不可用时的兜底）；只查已加载的书目，不触发下载
+pub async fn catalog_cover_url(book_id: &str) -> Option<String> {
+    let cache = BOOKS_CACHE.lock().await;
+    let book = cache.as_ref()?.books.iter().find(|book| book.id == book_id)?;
+    let url = select_cover_url(book.custom_properties.as_ref()?);
+    (!url.is_empty()).then_some(url)
+}
+
 async fn fetch_raw_books(version: &DataVersion) -> Result<Vec<RawBook>, String> {
     let requests = version
         .url_list()
@@ -190,30 +362,58 @@ pub async fn fetch_statistics(book_ids: &[String]) -> HashMap<String, (i64, i64)
         .collect()
 }
 
-// tag_path 形如 "id1/id2/.../idN"，判断是否包含 required 的连续子序列
-fn path_matches(tag_path: &str, required: &[String]) -> bool {
-    let parts: Vec<&str> = tag_path.split('/').collect();
-    parts
-        .windows(required.len())
-        .any(|window| window.iter().zip(required).all(|(part, id)| part == id))
-}
-
-pub fn filter_books<'a>(books: &'a [RawBook], required: &[String]) -> Vec<&'a RawBook> {
-    books
-        .iter()
-        .filter(|book| {
+/// 书目所属分类的名称链。一本书可能挂在多条路径下：优先取与 required 匹配的那条
+/// （即用户当前筛选的分类），否则取第一条能还原出名称的
+pub fn book_labels(
+    book: &RawBook,
+    names: &HashMap<String, String>,
+    required: &[String],
+) -> Vec<String> {
+    let matched = (!required.is_empty())
+        .then(|| book.tag_paths.iter().find(|path| path_matches(path, required)))
+        .flatten();
+    matched
+        .map(|path| tags::resolve_path(names, path))
+        .filter(|labels| !labels.is_empty())
+        .or_else(|| {
             book.tag_paths
                 .iter()
-                .any(|path| path_matches(path, required))
+                .map(|path| tags::resolve_path(names, path))
+                .find(|labels| !labels.is_empty())
         })
-        .collect()
+        .unwrap_or_default()
+}
+
+/// 「按分类保存」的相对目录：各级名称清洗后以 / 连接
+pub fn category_save_path(labels: &[String]) -> String {
+    labels
+        .iter()
+        .filter(|label| !label.is_empty())
+        .map(|label| sanitize_name(label))
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
-pub fn to_textbooks(books: Vec<&RawBook>, stats: &HashMap<String, (i64, i64)>) -> Vec<Textbook> {
+/// 按资源 id 从书目里还原分类名称（下载请求没带分类信息时用）
+pub async fn resolve_book_labels(book_id: &str) -> Option<Vec<String>> {
+    let catalog = get_raw_books().await.ok()?;
+    let book = catalog.books.iter().find(|book| book.id == book_id)?;
+    let tree = tags::fetch_tag_tree().await.ok()?;
+    let labels = book_labels(book, &tags::label_map(&tree.tree), &[]);
+    (!labels.is_empty()).then_some(labels)
+}
+
+pub fn to_textbooks(
+    books: Vec<&RawBook>,
+    stats: &HashMap<String, (i64, i64)>,
+    names: &HashMap<String, String>,
+    required: &[String],
+) -> Vec<Textbook> {
     books
         .into_iter()
         .map(|book| {
             let (total_uv, like_count) = stats.get(&book.id).copied().unwrap_or((0, 0));
+            let labels = book_labels(book, names, required);
             Textbook {
                 id: book.id.clone(),
                 cover_url: book
@@ -225,6 +425,8 @@ pub fn to_textbooks(books: Vec<&RawBook>, stats: &HashMap<String, (i64, i64)>) -
                 total_uv,
                 like_count,
                 download_url: format!("{DOWNLOAD_URL_PREFIX}{}{DOWNLOAD_URL_SUFFIX}", book.id),
+                save_path: category_save_path(&labels),
+                labels,
             }
         })
         .collect()
@@ -281,3 +483,63 @@ fn first_preview_slide(cp: &CustomProperties) -> Option<String> {
         .min_by_key(|(n, _)| *n)
         .map(|(_, url)| url)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_follow_the_selected_path() {
+        let names: HashMap<String, String> = [
+            ("xx", "小学"),
+            ("cz", "初中"),
+            ("yw", "语文"),
+            ("tb", "统编版（五·四学制）"),
+            ("g6", "六年级"),
+        ]
+        .into_iter()
+        .map(|(id, name)| (id.to_string(), name.to_string()))
+        .collect();
+        let book = RawBook {
+            id: "a".to_string(),
+            title: "语文 六年级上册".to_string(),
+            tag_paths: vec!["root/xx/yw/tb/g6".to_string(), "root/cz/yw/tb/g6".to_string()],
+            custom_properties: None,
+        };
+
+        let first = book_labels(&book, &names, &[]);
+        assert_eq!(first, vec!["小学", "语文", "统编版（五·四学制）", "六年级"]);
+        let selected = book_labels(&book, &names, &["cz".to_string(), "yw".to_string()]);
+        assert_eq!(selected[0], "初中");
+
+        let relative = category_save_path(&["高中".to_string(), "a/b:c".to_string()]);
+        assert_eq!(relative, "高中/a_b_c");
+    }
+
+    #[test]
+    fn newer_transcode_or_source_marks_a_new_version() {
+        let detail: ResourceDetail = serde_json::from_value(serde_json::json!({
+            "ti_items": [
+                {"ti_file_flag": "source", "ti_format": "pdf",
+                 "ti_storages": ["https://r1/assets/a.pkg/人教版.pdf"]},
+                {"ti_file_flag": "thumbnail_1", "ti_format": "jpg",
+                 "ti_storages": ["https://r1/assets/a.pkg/.t/zh-CN/1700000000/1.jpg"]},
+            ]
+        }))
+        .unwrap();
+        let recorded = detail_source_version(&detail);
+        assert_eq!(recorded.transcode_timestamp, 1700000000);
+        assert!(!recorded.is_newer_than(&recorded));
+
+        let retranscoded = SourceVersion {
+            transcode_timestamp: 1710000000,
+            ..recorded.clone()
+        };
+        assert!(retranscoded.is_newer_than(&recorded));
+        let reissued = SourceVersion {
+            source_url: "https://r1/assets/a.pkg/人教版（2024）.pdf".to_string(),
+            ..recorded.clone()
+        };
+        assert!(reissued.is_newer_than(&recorded));
+    }
+}
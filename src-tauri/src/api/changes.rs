@@ -0,0 +1,179 @@
+// 书目更新检测：get_raw_books 发现新的 module_version 时，与上一版书目逐本比对，
+// 得出新增、下架、改名、调整了分类（tag_paths 变化）的教材。
+// 最近一次的变化落盘保存，「有什么更新」命令与 catalog-updated 事件都取自这里。
+
+use crate::models::RawBook;
+use crate::storage;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+const CHANGES_CACHE_FILE: &str = "catalog_changes.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookChange {
+    pub id: String,
+    pub title: String,
+    // 改名前的标题（仅 retitled）
+    #[serde(default)]
+    pub previous_title: Option<String>,
+    pub tag_paths: Vec<String>,
+    // 调整前的分类路径（仅 moved）
+    #[serde(default)]
+    pub previous_tag_paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CatalogChanges {
+    pub old_version: u64,
+    pub new_version: u64,
+    // 检测到变化的时间（unix 秒）
+    pub detected_at: u64,
+    pub added: Vec<BookChange>,
+    pub removed: Vec<BookChange>,
+    pub retitled: Vec<BookChange>,
+    pub moved: Vec<BookChange>,
+}
+
+impl CatalogChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.retitled.is_empty()
+            && self.moved.is_empty()
+    }
+}
+
+static LATEST_CHANGES: Lazy<Mutex<Option<Arc<CatalogChanges>>>> = Lazy::new(|| Mutex::new(None));
+
+fn change_of(book: &RawBook) -> BookChange {
+    BookChange {
+        id: book.id.clone(),
+        title: book.title.clone(),
+        previous_title: None,
+        tag_paths: book.tag_paths.clone(),
+        previous_tag_paths: Vec::new(),
+    }
+}
+
+// tag_paths 的顺序不代表含义，按集合比较
+fn same_paths(a: &[String], b: &[String]) -> bool {
+    a.iter().collect::<BTreeSet<_>>() == b.iter().collect::<BTreeSet<_>>()
+}
+
+/// 逐本比对两版书目。改名与调整分类可能同时发生，两个列表里都会出现
+pub fn diff_catalogs(old: &[RawBook], new: &[RawBook]) -> CatalogChanges {
+    let old_by_id: HashMap<&str, &RawBook> = old.iter().map(|b| (b.id.as_str(), b)).collect();
+    let new_by_id: HashMap<&str, &RawBook> = new.iter().map(|b| (b.id.as_str(), b)).collect();
+
+    let mut changes = CatalogChanges::default();
+    for book in new {
+        let Some(prev) = old_by_id.get(book.id.as_str()) else {
+            changes.added.push(change_of(book));
+            continue;
+        };
+        if prev.title != book.title {
+            changes.retitled.push(BookChange {
+                previous_title: Some(prev.title.clone()),
+                ..change_of(book)
+            });
+        }
+        if !same_paths(&prev.tag_paths, &book.tag_paths) {
+            changes.moved.push(BookChange {
+                previous_tag_paths: prev.tag_paths.clone(),
+                ..change_of(book)
+            });
+        }
+    }
+    changes.removed = old
+        .iter()
+        .filter(|b| !new_by_id.contains_key(b.id.as_str()))
+        .map(change_of)
+        .collect();
+    changes
+}
+
+/// 记录一次版本更替的变化；两版内容完全一致时返回 None
+pub async fn record(
+    old_version: u64,
+    old: &[RawBook],
+    new_version: u64,
+    new: &[RawBook],
+) -> Option<Arc<CatalogChanges>> {
+    let mut changes = diff_catalogs(old, new);
+    if changes.is_empty() {
+        return None;
+    }
+    changes.old_version = old_version;
+    changes.new_version = new_version;
+    changes.detected_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    log::info!(
+        "书目更新 {old_version} → {new_version}: 新增 {}，下架 {}，改名 {}，调整分类 {}",
+        changes.added.len(),
+        changes.removed.len(),
+        changes.retitled.len(),
+        changes.moved.len()
+    );
+
+    if let Err(e) = storage::write_json(CHANGES_CACHE_FILE, &changes).await {
+        log::warn!("保存书目变化失败: {e}");
+    }
+    let changes = Arc::new(changes);
+    *LATEST_CHANGES.lock().unwrap() = Some(Arc::clone(&changes));
+    Some(changes)
+}
+
+/// 最近一次检测到的书目变化（本次运行内或上次运行落盘的）
+pub async fn latest() -> Option<Arc<CatalogChanges>> {
+    if let Some(changes) = LATEST_CHANGES.lock().unwrap().clone() {
+        return Some(changes);
+    }
+    let stored = Arc::new(storage::read_json::<CatalogChanges>(CHANGES_CACHE_FILE).await?);
+    *LATEST_CHANGES.lock().unwrap() = Some(Arc::clone(&stored));
+    Some(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, title: &str, paths: &[&str]) -> RawBook {
+        RawBook {
+            id: id.to_string(),
+            title: title.to_string(),
+            tag_paths: paths.iter().map(|p| p.to_string()).collect(),
+            custom_properties: None,
+        }
+    }
+
+    #[test]
+    fn reports_added_removed_retitled_and_moved() {
+        let old = vec![
+            book("a", "语文 一年级上册", &["c/s/v/g1"]),
+            book("b", "数学 一年级上册", &["c/m/v/g1"]),
+            book("c", "英语", &["c/e/v/g1", "c/e/v/g2"]),
+        ];
+        let new = vec![
+            book("a", "语文 一年级上册（2024 修订）", &["c/s/v/g1"]),
+            book("c", "英语", &["c/e/v/g2", "c/e/v/g1"]),
+            book("d", "科学", &["c/k/v/g1"]),
+            book("b2", "数学", &["c/m/v2/g1"]),
+        ];
+        let changes = diff_catalogs(&old, &new);
+        let ids = |list: &[BookChange]| list.iter().map(|c| c.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&changes.added), vec!["d", "b2"]);
+        assert_eq!(ids(&changes.removed), vec!["b"]);
+        assert_eq!(ids(&changes.retitled), vec!["a"]);
+        assert_eq!(changes.retitled[0].previous_title.as_deref(), Some("语文 一年级上册"));
+        // 只是顺序不同不算调整分类
+        assert!(changes.moved.is_empty());
+
+        let moved = diff_catalogs(&old[..1], &[book("a", "语文 一年级上册", &["c/s/v2/g1"])]);
+        assert_eq!(moved.moved[0].previous_tag_paths, vec!["c/s/v/g1"]);
+        assert!(moved.retitled.is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+// 教材的配套资源：教材详情 relations 里挂的课件/教案/习题、配套音频清单，以及同步课堂课时。
+// 每组都是一个 CourseParseResult，沿用课程资源的下载与续传流程；分类目录取教材自己的分类，
+// 开启「按分类保存」时与教材 PDF 落在同一目录下。
+
+use super::{books, courses, detail_cache, lessons};
+use crate::models::CourseParseResult;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::command;
+
+const AUDIO_URL: &str = "https://s-file-2.ykt.cbern.com.cn/zxx/ndrs/resources";
+const PAGE_URL: &str = "https://basic.smartedu.cn/tchMaterial/detail";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TextbookCompanions {
+    pub book_id: String,
+    pub title: String,
+    pub category_path: Vec<String>,
+    // 配套资源分组：教材详情里的关联资源、配套音频、各同步课堂课时
+    pub groups: Vec<CourseParseResult>,
+    // 没能取到的分组（网络或数据异常），与「本来就没有」区分开，由前端提示用户
+    pub warnings: Vec<String>,
+}
+
+// 平台对不存在的清单返回 404：视为该教材没有这类资源
+fn is_not_found(error: &str) -> bool {
+    error.contains("HTTP 404")
+}
+
+fn page_url(book_id: &str) -> String {
+    format!("{PAGE_URL}?contentType=assets_document&contentId={book_id}")
+}
+
+// 教材详情 relations 里的关联资源
+fn relation_group(
+    book_id: &str,
+    title: &str,
+    detail: &Value,
+    category_path: &[String],
+) -> Option<CourseParseResult> {
+    let group_title = format!("{title} 配套资源");
+    let resources = courses::relation_resources(detail, &group_title);
+    (!resources.is_empty()).then(|| CourseParseResult {
+        title: group_title,
+        source_url: page_url(book_id),
+        detail_url: books::detail_url(book_id),
+        category_path: category_path.to_vec(),
+        resources,
+    })
+}
+
+async fn audio_group(
+    book_id: &str,
+    title: &str,
+    category_path: &[String],
+) -> Result<Option<CourseParseResult>, String> {
+    let url = format!("{AUDIO_URL}/{book_id}/relation_audios.json");
+    let items = match detail_cache::get(&url).await {
+        Ok(items) => items,
+        // 多数教材没有配套音频
+        Err(e) if is_not_found(&e) => return Ok(None),
+        Err(e) => return Err(format!("获取配套音频失败: {e}")),
+    };
+    let items = items
+        .as_array()
+        .ok_or_else(|| "配套音频清单格式异常".to_string())?;
+    let group_title = format!("{title} 配套音频");
+    let resources = courses::item_resources(items, &group_title);
+    Ok((!resources.is_empty()).then(|| CourseParseResult {
+        title: group_title,
+        source_url: page_url(book_id),
+        detail_url: url,
+        category_path: category_path.to_vec(),
+        resources,
+    }))
+}
+
+// 课时按教材归档：分类目录后再加一级「书名 同步课堂」，课时各占一个子目录
+fn regroup_lessons(
+    mut lessons: Vec<CourseParseResult>,
+    title: &str,
+    category_path: &[String],
+) -> Vec<CourseParseResult> {
+    let mut path = category_path.to_vec();
+    path.push(format!("{title} 同步课堂"));
+    for lesson in &mut lessons {
+        lesson.category_path = path.clone();
+    }
+    lessons
+}
+
+/// 教材的全部配套资源。没有的分组跳过，取失败的分组记入 warnings；都没有时 groups 为空
+#[command]
+pub async fn fetch_companion_resources(book_id: String) -> Result<TextbookCompanions, String> {
+    if !lessons::is_safe_id(&book_id) {
+        return Err(format!("无效的教材 id: {book_id}"));
+    }
+    let detail = detail_cache::get(&books::detail_url(&book_id))
+        .await
+        .map_err(|e| format!("获取教材详情失败: {e}"))?;
+    let title = detail
+        .get("title")
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("未命名教材")
+        .to_string();
+    let category_path = books::resolve_book_labels(&book_id)
+        .await
+        .unwrap_or_default();
+
+    let mut groups = Vec::new();
+    let mut warnings = Vec::new();
+    groups.extend(relation_group(&book_id, &title, &detail, &category_path));
+    match audio_group(&book_id, &title, &category_path).await {
+        Ok(group) => groups.extend(group),
+        Err(e) => {
+            log::warn!("{e}");
+            warnings.push(e);
+        }
+    }
+    match lessons::parse_lessons(&book_id, None).await {
+        Ok(lessons) => groups.extend(regroup_lessons(lessons, &title, &category_path)),
+        Err(e) if e == lessons::NO_LESSONS || is_not_found(&e) => {
+            log::info!("教材没有同步课堂课时: {e}");
+        }
+        Err(e) => {
+            log::warn!("获取同步课堂课时失败: {e}");
+            warnings.push(format!("获取同步课堂课时失败: {e}"));
+        }
+    }
+
+    log::info!(
+        "《{title}》配套资源：{} 组，{} 个资源",
+        groups.len(),
+        groups.iter().map(|g| g.resources.len()).sum::<usize>()
+    );
+    Ok(TextbookCompanions {
+        book_id,
+        title,
+        category_path,
+        groups,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn groups_relations_under_the_textbook_category() {
+        let detail = json!({
+            "id": "book",
+            "title": "语文 七年级上册",
+            "ti_items": [{ "ti_format": "pdf", "ti_storages": ["https://r1-ndr.ykt.cbern.com.cn/a/pdf.pdf"] }],
+            "relations": {
+                "lesson_plan": [{
+                    "id": "r1",
+                    "title": "春 教学设计",
+                    "ti_items": [{
+                        "ti_file_flag": "source",
+                        "ti_format": "docx",
+                        "ti_storages": ["https://r1-ndr.ykt.cbern.com.cn/r1/source.docx"]
+                    }]
+                }]
+            }
+        });
+        let path = vec!["初中".to_string(), "语文".to_string()];
+        let group = relation_group("book", "语文 七年级上册", &detail, &path).unwrap();
+        // 顶层 ti_items 是教材 PDF 本身，不算配套资源
+        assert_eq!(group.resources.len(), 1);
+        assert_eq!(group.resources[0].title, "春 教学设计");
+        assert_eq!(group.category_path, path);
+        assert!(
+            group
+                .detail_url
+                .ends_with("/tch_material/details/book.json")
+        );
+
+        let lessons = regroup_lessons(
+            vec![CourseParseResult {
+                title: "春".to_string(),
+                source_url: String::new(),
+                detail_url: String::new(),
+                category_path: vec!["初中".to_string()],
+                resources: Vec::new(),
+            }],
+            "语文 七年级上册",
+            &path,
+        );
+        assert_eq!(
+            lessons[0].category_path,
+            vec!["初中", "语文", "语文 七年级上册 同步课堂"]
+        );
+    }
+
+    #[test]
+    fn only_404_means_no_resources() {
+        assert!(is_not_found("请求失败 https://a/relation_audios.json: HTTP 404 Not Found"));
+        assert!(!is_not_found("请求失败 https://a/relation_audios.json: HTTP 502 Bad Gateway"));
+        assert!(!is_not_found("error sending request: operation timed out"));
+    }
+}
@@ -1,6 +1,7 @@
-use crate::http;
+use super::detail_cache;
 use crate::models::{CourseParseResult, CourseResource};
 use serde_json::Value;
+use std::time::Duration;
 use url::Url;
 
 // 平台各类课程页 URL → 详情接口的映射。绝大多数详情 JSON 的形态一致：
@@ -175,6 +176,11 @@ fn resolve_route(url: &Url) -> Option<Route> {
     None
 }
 
+/// 课程页 URL 对应的详情接口地址
+pub(super) fn detail_url_of(url: &Url) -> Option<String> {
+    resolve_route(url).map(|route| route.detail_url)
+}
+
 // resourceType 直接拼进接口路径，限定为小写字母/数字/下划线，防止拼出越权路径
 fn is_safe_segment(s: &str) -> bool {
     !s.is_empty()
@@ -228,8 +234,8 @@ fn extract_resource(obj: &Value, fallback_title: &str) -> Option<CourseResource>
 
     // 视频优先走 m3u8；声明是视频却没有 m3u8（少数直链 mp4）时退回普通文件下载，
     // 否则整条资源会被静默丢弃。
-    let (download_url, item_format, is_video) = if has_m3u8 {
-        (pick_video_url(ti_items)?, String::new(), true)
+    let (item, item_format, is_video) = if has_m3u8 {
+        (pick_video_item(ti_items)?, String::new(), true)
     } else {
         let item = pick_file_item(ti_items)?;
         let fmt = item
@@ -237,8 +243,15 @@ fn extract_resource(obj: &Value, fallback_title: &str) -> Option<CourseResource>
             .and_then(Value::as_str)
             .unwrap_or("")
             .to_lowercase();
-        (first_storage(item)?, fmt, false)
+        (item, fmt, false)
     };
+    let download_url = first_storage(item)?;
+    let mirror_urls = all_storages(item);
+    let ti_file_flag = item
+        .get("ti_file_flag")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_string();
 
     // 关键：扩展名以实际下载到的文件为准，而不是 custom_properties.format。
     // 平台上 format 标 docx/pptx 的课件，ti_items 里往往只有转码后的 pdf.pdf，
@@ -263,11 +276,13 @@ fn extract_resource(obj: &Value, fallback_title: &str) -> Option<CourseResource>
         download_url,
         is_video,
         cover_url,
+        ti_file_flag,
+        mirror_urls,
     })
 }
 
 // 从 ti_items 里挑 m3u8 播放列表，优先 720p，其次任意 m3u8。
-fn pick_video_url(ti_items: &[Value]) -> Option<String> {
+fn pick_video_item(ti_items: &[Value]) -> Option<&Value> {
     let m3u8_items: Vec<&Value> = ti_items
         .iter()
         .filter(|it| it.get("ti_format").and_then(Value::as_str) == Some("m3u8"))
@@ -278,7 +293,7 @@ fn pick_video_url(ti_items: &[Value]) -> Option<String> {
         .find(|it| it.get("ti_file_flag").and_then(Value::as_str) == Some("href-720p-m3u8"))
         .or_else(|| m3u8_items.first())?;
 
-    first_storage(chosen)
+    Some(chosen)
 }
 
 // ti_items 里除正文外还混着缩略图、AI 字幕/摘要、白板工程等附属项，兜底时要跳过
@@ -343,6 +358,20 @@ fn first_storage(item: &Value) -> Option<String> {
         .map(str::to_string)
 }
 
+// 全部镜像地址（第一个即 first_storage）
+fn all_storages(item: &Value) -> Vec<String> {
+    item.get("ti_storages")
+        .and_then(Value::as_array)
+        .map(|a| {
+            a.iter()
+                .filter_map(Value::as_str)
+                .filter(|s| s.starts_with("http"))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 fn url_extension(url: &str) -> Option<String> {
     let path = url.split('?').next().unwrap_or(url);
     let last = path.rsplit('/').next()?;
@@ -458,6 +487,27 @@ fn collect_resources(detail: &Value, source: &Source, course_title: &str) -> Vec
         collect_all_relations(detail, course_title, &mut resources);
     }
 
+    disambiguate(&mut resources);
+    resources
+}
+
+/// 详情 relations 下的全部资源（不含顶层 ti_items），教材详情挂的配套资源用
+pub(super) fn relation_resources(detail: &Value, fallback_title: &str) -> Vec<CourseResource> {
+    let mut resources = Vec::new();
+    collect_all_relations(detail, fallback_title, &mut resources);
+    disambiguate(&mut resources);
+    resources
+}
+
+/// 资源对象数组里的资源（如教材的配套音频清单）
+pub(super) fn item_resources(items: &[Value], fallback_title: &str) -> Vec<CourseResource> {
+    let mut resources = Vec::new();
+    collect_into(items, fallback_title, &mut resources);
+    disambiguate(&mut resources);
+    resources
+}
+
+fn disambiguate(resources: &mut Vec<CourseResource>) {
     // 同一资源可能在顶层和 relations 里各出现一次，按下载地址去重
     let mut seen = std::collections::HashSet::new();
     resources.retain(|r| seen.insert(r.download_url.clone()));
@@ -465,7 +515,7 @@ fn collect_resources(detail: &Value, source: &Source, course_title: &str) -> Vec
     // 同一课程的资源都存进同一目录，标题+格式相同会写到同一个文件：
     // 并发下载时互相覆盖甚至写坏。重名的追加序号。
     let mut used = std::collections::HashSet::new();
-    for res in &mut resources {
+    for res in resources.iter_mut() {
         let key = |t: &str, f: &str| format!("{}.{}", t.to_lowercase(), f.to_lowercase());
         if used.insert(key(&res.title, &res.format)) {
             continue;
@@ -478,20 +528,113 @@ fn collect_resources(detail: &Value, source: &Source, course_title: &str) -> Vec
             }
         }
     }
+}
 
-    resources
+// 平台 CDN 域名（资源桶、私有桶、详情桶都在其下）
+const CDN_HOST_SUFFIXES: &[&str] = &[".ykt.cbern.com.cn", ".ykt.eduyun.cn"];
+
+pub fn is_cdn_host(host: &str) -> bool {
+    CDN_HOST_SUFFIXES.iter().any(|suffix| host.ends_with(suffix))
+}
+
+// 直链文件名里常见的无意义名字（如 .../videos/720p/index.m3u8），遇到时改用上一级目录名
+fn is_generic_stem(stem: &str) -> bool {
+    matches!(
+        stem.to_lowercase().as_str(),
+        "index" | "playlist" | "master" | "pdf" | "video" | "source"
+    ) || stem.trim_end_matches('p').chars().all(|c| c.is_ascii_digit())
+}
+
+// 从直链推一个可读标题：取最后一段路径的文件名（去扩展名、百分号解码）
+fn title_from_url(url: &Url) -> Option<String> {
+    let decode = |s: &str| percent_encoding::percent_decode_str(s).decode_utf8_lossy().into_owned();
+    let segments: Vec<String> = url
+        .path_segments()?
+        .filter(|s| !s.is_empty())
+        .map(decode)
+        .collect();
+    let (last, parents) = segments.split_last()?;
+    let stem = last.rsplit_once('.').map_or(last.as_str(), |(stem, _)| stem);
+    let stem = if is_generic_stem(stem) {
+        let parent = parents.iter().rev().find(|p| !is_generic_stem(p))?;
+        parent.rsplit_once('.').map_or(parent.as_str(), |(stem, _)| stem)
+    } else {
+        stem
+    };
+    let stem = strip_known_extension(stem.trim());
+    (!stem.is_empty()).then(|| stem.to_string())
+}
+
+// 直链资源：devtools 里复制的 .m3u8 播放列表（任意域名），或平台 CDN 上的
+// 媒体/文档文件。新上线的页面类型不必等路由表更新也能下载。
+fn direct_resource(url: &Url, name: Option<&str>) -> Option<CourseResource> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let ext = url_extension(url.as_str())?;
+    let is_video = ext == "m3u8";
+    // m3u8 接受任意主机（令牌只随平台域名的请求发出，见 task::with_auth）；
+    // 其他直链只认平台 CDN 上已知类型的文件
+    if !is_video {
+        let on_cdn = url.host_str().is_some_and(is_cdn_host);
+        if !on_cdn || !KNOWN_EXTENSIONS.contains(&ext.as_str()) {
+            return None;
+        }
+    }
+
+    let title = name
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| strip_known_extension(s).to_string())
+        .or_else(|| title_from_url(url))
+        .unwrap_or_else(|| "未命名资源".to_string());
+
+    Some(CourseResource {
+        id: String::new(),
+        title,
+        format: if is_video { "mp4".to_string() } else { ext },
+        download_url: url.to_string(),
+        is_video,
+        cover_url: String::new(),
+        ti_file_flag: String::new(),
+        mirror_urls: Vec::new(),
+    })
 }
 
 /// 解析课程页 URL，返回其下所有可下载资源。
+/// 也接受 m3u8 / CDN 文件直链，作为单资源结果返回；name 为用户指定的标题（可空，默认取自 URL）。
 #[tauri::command]
-pub async fn parse_course_url(url: String) -> Result<CourseParseResult, String> {
+pub async fn parse_course_url(
+    url: String,
+    name: Option<String>,
+) -> Result<CourseParseResult, String> {
     let parsed = Url::parse(url.trim()).map_err(|e| format!("无效的链接: {e}"))?;
-    let route = resolve_route(&parsed).ok_or_else(|| {
-        "暂不支持该链接类型，请粘贴课程/视频/课件页面的地址".to_string()
-    })?;
+    let Some(route) = resolve_route(&parsed) else {
+        let resource = direct_resource(&parsed, name.as_deref()).ok_or_else(|| {
+            "暂不支持该链接类型，请粘贴课程/视频/课件页面的地址，或 m3u8 / 平台文件直链".to_string()
+        })?;
+        log::info!("按直链解析: {}", resource.download_url);
+        return Ok(CourseParseResult {
+            title: resource.title.clone(),
+            source_url: parsed.to_string(),
+            detail_url: String::new(),
+            category_path: Vec::new(),
+            resources: vec![resource],
+        });
+    };
+
+    parse_route(&parsed, route).await
+}
+
+/// 解析课程页 URL（不接受直链），同步课堂目录批量取课时资源时用
+pub(super) async fn parse_page(parsed: &Url) -> Result<CourseParseResult, String> {
+    let route = resolve_route(parsed).ok_or_else(|| format!("暂不支持该链接类型: {parsed}"))?;
+    parse_route(parsed, route).await
+}
 
+async fn parse_route(parsed: &Url, route: Route) -> Result<CourseParseResult, String> {
     log::info!("解析课程详情: {}", route.detail_url);
-    let detail: Value = http::get_json(&route.detail_url)
+    let detail = detail_cache::get(&route.detail_url)
         .await
         .map_err(|e| format!("获取课程详情失败: {e}"))?;
 
@@ -510,11 +653,72 @@ pub async fn parse_course_url(url: String) -> Result<CourseParseResult, String>
 
     Ok(CourseParseResult {
         title: course_title,
+        source_url: parsed.to_string(),
+        detail_url: route.detail_url,
         category_path: extract_category_path(&detail),
         resources,
     })
 }
 
+// 在详情里按 id 找资源对象：顶层或任意 relations 数组里；
+// 详情本身是资源数组（配套音频清单）时在数组里找
+fn find_resource_object<'a>(detail: &'a Value, resource_id: &str) -> Option<&'a Value> {
+    if detail.get("id").and_then(Value::as_str) == Some(resource_id) {
+        return Some(detail);
+    }
+    if let Some(items) = detail.as_array() {
+        return items
+            .iter()
+            .find(|obj| obj.get("id").and_then(Value::as_str) == Some(resource_id));
+    }
+    detail
+        .get("relations")
+        .and_then(Value::as_object)?
+        .values()
+        .filter_map(Value::as_array)
+        .flatten()
+        .find(|obj| obj.get("id").and_then(Value::as_str) == Some(resource_id))
+}
+
+fn storages_by_flag(obj: &Value, ti_file_flag: &str) -> Vec<String> {
+    obj.get("ti_items")
+        .and_then(Value::as_array)
+        .and_then(|items| {
+            items
+                .iter()
+                .find(|it| it.get("ti_file_flag").and_then(Value::as_str) == Some(ti_file_flag))
+        })
+        .map(all_storages)
+        .unwrap_or_default()
+}
+
+// 重新取地址时，这么久之内拉过的详情直接复用（整单元入队时各资源多半同属一个详情地址）
+const REFRESH_REUSE: Duration = Duration::from_secs(10 * 60);
+
+/// 按资源身份重新取下载地址（含各镜像，第一个为主地址）：签名地址过期、
+/// 播放列表换了地址时，续传前先从详情接口拿到同一 ti_item 的最新地址
+pub async fn refresh_resource_urls(
+    detail_url: &str,
+    resource_id: &str,
+    ti_file_flag: &str,
+) -> Result<Vec<String>, String> {
+    let parsed = Url::parse(detail_url).map_err(|e| format!("无效的详情地址: {e}"))?;
+    if !parsed.host_str().is_some_and(is_cdn_host) {
+        return Err(format!("详情地址不在平台域名下: {detail_url}"));
+    }
+    // 续传时地址可能已过期，不用缓存的详情；同一批任务共用一次刚拉到的结果
+    let detail = detail_cache::fetch_recent(detail_url, REFRESH_REUSE)
+        .await
+        .map_err(|e| format!("获取课程详情失败: {e}"))?;
+    let obj = find_resource_object(&detail, resource_id)
+        .ok_or_else(|| format!("详情中找不到资源 {resource_id}"))?;
+    let urls = storages_by_flag(obj, ti_file_flag);
+    if urls.is_empty() {
+        return Err(format!("资源 {resource_id} 没有 {ti_file_flag} 地址"));
+    }
+    Ok(urls)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -612,6 +816,8 @@ mod tests {
         assert!(res.is_video);
         assert_eq!(res.format, "mp4");
         assert_eq!(res.download_url, "https://h/v/720.m3u8");
+        assert_eq!(res.ti_file_flag, "href-720p-m3u8");
+        assert_eq!(res.mirror_urls, vec!["https://h/v/720.m3u8"]);
     }
 
     // 声明是视频却没有 m3u8（直链 mp4）时不再被丢弃
@@ -756,6 +962,32 @@ mod tests {
         assert_eq!(titles, vec!["课件", "课件 (2)", "课件 (3)"]);
     }
 
+    #[test]
+    fn accepts_direct_m3u8_from_any_host() {
+        let url = Url::parse("https://cdn.example.com/v/%E6%9C%89%E7%90%86%E6%95%B0/720p/index.m3u8")
+            .unwrap();
+        let res = direct_resource(&url, None).unwrap();
+        assert!(res.is_video);
+        assert_eq!(res.format, "mp4");
+        assert_eq!(res.title, "有理数");
+    }
+
+    // 非视频直链只认平台 CDN，且扩展名要是已知的媒体/文档类型
+    #[test]
+    fn direct_files_must_be_on_platform_cdn() {
+        let cdn = Url::parse("https://r1-ndr.ykt.cbern.com.cn/edu_product/esp/assets/abc.pkg/讲义.pdf")
+            .unwrap();
+        let res = direct_resource(&cdn, Some("第一单元 讲义.pdf")).unwrap();
+        assert!(!res.is_video);
+        assert_eq!(res.format, "pdf");
+        assert_eq!(res.title, "第一单元 讲义");
+
+        let other = Url::parse("https://example.com/a/讲义.pdf").unwrap();
+        assert!(direct_resource(&other, None).is_none());
+        let page = Url::parse("https://basic.smartedu.cn/tchMaterial/detail?id=1").unwrap();
+        assert!(direct_resource(&page, None).is_none());
+    }
+
     // 同一资源在顶层和 relations 里各挂一次时只保留一份
     #[test]
     fn same_download_url_is_deduped() {
@@ -765,4 +997,17 @@ mod tests {
         assert_eq!(resources.len(), 1);
         assert_eq!(resources[0].title, "微课视频");
     }
+
+    // 续传时按 id + ti_file_flag 在新详情里找回同一资源的地址
+    #[test]
+    fn finds_resource_by_identity_in_relations() {
+        let mut detail = doc_item("课", "pdf", "https://h/top.pdf");
+        detail["relations"] = json!({
+            "lesson_2": [doc_item("课件", "pdf", "https://h/b.pdf?sign=new")],
+        });
+        let obj = find_resource_object(&detail, "https://h/b.pdf?sign=new").unwrap();
+        assert_eq!(storages_by_flag(obj, "pdf"), vec!["https://h/b.pdf?sign=new"]);
+        assert!(storages_by_flag(obj, "href").is_empty());
+        assert!(find_resource_object(&detail, "missing").is_none());
+    }
 }
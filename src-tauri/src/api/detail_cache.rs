@@ -0,0 +1,212 @@
+// 资源详情 JSON 的缓存，教材详情（封面、源 PDF 地址）与课程详情共用：
+// - 内存里是容量有限的 LRU
+// - 磁盘上每条一个文件（cache/details/<地址 md5>.json），超出上限时按修改时间淘汰最旧的，
+//   命中时刷新修改时间
+// 书目 module_version 变化时整体作废：平台重新转码后详情里的地址与封面会跟着变。
+// 需要最新地址时（续传）走 fetch_recent：同一地址短时间内只真正请求一次，并发的调用等同一次结果。
+
+use crate::{http, storage};
+use md5::{Digest, Md5};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs;
+
+const MEMORY_CAPACITY: usize = 256;
+const DISK_CAPACITY: usize = 4096;
+// 每写入这么多条检查一次磁盘条目数
+const PRUNE_EVERY: usize = 64;
+const DETAIL_DIR: &str = "details";
+const VERSION_FILE: &str = "details_version.json";
+
+struct Entry {
+    value: Arc<Value>,
+    last_used: u64,
+}
+
+struct Lru {
+    entries: HashMap<String, Entry>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            tick: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<Value>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.tick;
+        Some(Arc::clone(&entry.value))
+    }
+
+    fn insert(&mut self, key: &str, value: Arc<Value>) {
+        self.tick += 1;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(key) {
+            // 容量只有几百，线性找最久未用的即可
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+struct DiskState {
+    // 磁盘上的详情所属的书目版本；None 表示还没读过
+    version: Option<u64>,
+    writes: usize,
+}
+
+static MEMORY: Lazy<std::sync::Mutex<Lru>> =
+    Lazy::new(|| std::sync::Mutex::new(Lru::new(MEMORY_CAPACITY)));
+static DISK: Lazy<tokio::sync::Mutex<DiskState>> = Lazy::new(|| {
+    tokio::sync::Mutex::new(DiskState {
+        version: None,
+        writes: 0,
+    })
+});
+
+// 详情地址 → 最近一次从网络拉到的时刻与结果。每个地址一把异步锁，并发请求同一地址时只有一个真正发出
+type RecentSlot = Arc<tokio::sync::Mutex<Option<(Instant, Arc<Value>)>>>;
+static RECENT: Lazy<std::sync::Mutex<HashMap<String, RecentSlot>>> = Lazy::new(Default::default);
+
+fn entry_name(url: &str) -> String {
+    format!("{DETAIL_DIR}/{}.json", hex::encode(Md5::digest(url.as_bytes())))
+}
+
+/// 书目版本变化时清空详情缓存（内存与磁盘）；每次拉到 data_version 后调用
+pub async fn sync_version(module_version: u64) {
+    let mut disk = DISK.lock().await;
+    if disk.version.is_none() {
+        disk.version = storage::read_json::<u64>(VERSION_FILE).await;
+    }
+    if disk.version == Some(module_version) {
+        return;
+    }
+    if disk.version.is_some() {
+        log::info!("书目版本变化，清空资源详情缓存");
+    }
+    clear_entries().await;
+    disk.version = Some(module_version);
+    if let Err(e) = storage::write_json(VERSION_FILE, &module_version).await {
+        log::warn!("保存详情缓存版本失败: {e}");
+    }
+}
+
+async fn clear_entries() {
+    MEMORY.lock().unwrap().clear();
+    if let Some(dir) = storage::cache_path(DETAIL_DIR) {
+        let _ = fs::remove_dir_all(dir).await;
+    }
+}
+
+pub async fn clear() {
+    clear_entries().await;
+    DISK.lock().await.version = None;
+    storage::remove(VERSION_FILE).await;
+}
+
+/// 取详情 JSON：内存 → 磁盘 → 网络
+pub async fn get(url: &str) -> Result<Arc<Value>, String> {
+    if let Some(value) = MEMORY.lock().unwrap().get(url) {
+        return Ok(value);
+    }
+    let name = entry_name(url);
+    if let Some(value) = storage::read_json::<Value>(&name).await {
+        if let Some(path) = storage::cache_path(&name) {
+            tokio::task::spawn_blocking(move || storage::touch(&path));
+        }
+        let value = Arc::new(value);
+        MEMORY.lock().unwrap().insert(url, Arc::clone(&value));
+        return Ok(value);
+    }
+    fetch_fresh(url).await
+}
+
+/// 绕过缓存重新请求并更新缓存（签名地址过期等需要最新详情的场合）
+pub async fn fetch_fresh(url: &str) -> Result<Arc<Value>, String> {
+    let value = Arc::new(http::get_json::<Value>(url).await?);
+    MEMORY.lock().unwrap().insert(url, Arc::clone(&value));
+
+    let mut disk = DISK.lock().await;
+    if let Err(e) = storage::write_json(&entry_name(url), value.as_ref()).await {
+        log::debug!("写入详情缓存失败: {e}");
+        return Ok(value);
+    }
+    disk.writes += 1;
+    if disk.writes % PRUNE_EVERY == 0 {
+        if let Some(dir) = storage::cache_path(DETAIL_DIR) {
+            tokio::task::spawn_blocking(move || {
+                storage::prune_dir(&dir, DISK_CAPACITY, u64::MAX);
+            });
+        }
+    }
+    Ok(value)
+}
+
+/// 取 max_age 之内从网络拉到过的详情，没有就重新请求（同 fetch_fresh）。
+/// 批量续传时同一详情地址只请求一次
+pub async fn fetch_recent(url: &str, max_age: Duration) -> Result<Arc<Value>, String> {
+    let slot = {
+        let mut recent = RECENT.lock().unwrap();
+        // 顺手清掉过期且没人在用的条目
+        recent.retain(|_, slot| {
+            Arc::strong_count(slot) > 1
+                || slot
+                    .try_lock()
+                    .is_ok_and(|s| s.as_ref().is_some_and(|(at, _)| at.elapsed() < max_age))
+        });
+        Arc::clone(recent.entry(url.to_string()).or_default())
+    };
+    let mut slot = slot.lock().await;
+    if let Some((at, value)) = slot.as_ref() {
+        if at.elapsed() < max_age {
+            return Ok(Arc::clone(value));
+        }
+    }
+    let value = fetch_fresh(url).await?;
+    *slot = Some((Instant::now(), Arc::clone(&value)));
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut lru = Lru::new(2);
+        lru.insert("a", Arc::new(json!(1)));
+        lru.insert("b", Arc::new(json!(2)));
+        assert!(lru.get("a").is_some());
+        lru.insert("c", Arc::new(json!(3)));
+        assert!(lru.get("b").is_none());
+        assert_eq!(lru.get("a").as_deref(), Some(&json!(1)));
+        assert_eq!(lru.get("c").as_deref(), Some(&json!(3)));
+    }
+}
@@ -0,0 +1,235 @@
+// 导出教材清单：把当前筛选或搜索得到的列表写成 CSV / JSON / XLSX，供教务整理用书表。
+// 列表与本地下载状态都由前端传入（状态只有前端的下载池知道），格式按文件扩展名决定。
+
+use crate::models::Textbook;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use tauri::command;
+use tokio::fs;
+
+const HEADERS: [&str; 9] = [
+    "ID", "书名", "分类", "下载地址", "封面地址", "浏览量", "点赞数", "下载状态", "本地文件",
+];
+
+// 前端下载池里该教材的状态（按 download_url 对应）
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LocalDownload {
+    pub status: String,
+    #[serde(default)]
+    pub file_path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportRow<'a> {
+    id: &'a str,
+    title: &'a str,
+    labels: &'a [String],
+    download_url: &'a str,
+    cover_url: &'a str,
+    total_uv: i64,
+    like_count: i64,
+    // 没有下载记录时为 null
+    download_status: Option<&'a str>,
+    file_path: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Csv,
+    Json,
+    Xlsx,
+}
+
+impl ExportFormat {
+    fn from_path(path: &Path) -> Result<Self, String> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match ext.as_deref() {
+            Some("csv") => Ok(Self::Csv),
+            Some("json") => Ok(Self::Json),
+            Some("xlsx") => Ok(Self::Xlsx),
+            _ => Err("不支持的导出格式，请使用 .csv / .json / .xlsx".to_string()),
+        }
+    }
+}
+
+fn status_label(status: &str) -> &str {
+    match status {
+        "completed" => "已下载",
+        "downloading" => "下载中",
+        "queued" => "排队中",
+        "paused" => "已暂停",
+        "interrupted" => "已中断",
+        "failed" => "下载失败",
+        "idle" => "",
+        other => other,
+    }
+}
+
+fn rows<'a>(
+    textbooks: &'a [Textbook],
+    downloads: &'a HashMap<String, LocalDownload>,
+) -> Vec<ExportRow<'a>> {
+    textbooks
+        .iter()
+        .map(|book| {
+            let local = downloads
+                .get(&book.download_url)
+                .filter(|local| local.status != "idle");
+            ExportRow {
+                id: &book.id,
+                title: &book.title,
+                labels: &book.labels,
+                download_url: &book.download_url,
+                cover_url: &book.cover_url,
+                total_uv: book.total_uv,
+                like_count: book.like_count,
+                download_status: local.map(|local| local.status.as_str()),
+                file_path: local
+                    .map(|local| local.file_path.as_str())
+                    .filter(|path| !path.is_empty()),
+            }
+        })
+        .collect()
+}
+
+// 表格里每行的文本列（CSV 与 XLSX 共用）；浏览量、点赞数在 XLSX 里按数字写
+fn text_cells(row: &ExportRow) -> [String; 9] {
+    [
+        row.id.to_string(),
+        row.title.to_string(),
+        row.labels.join(" / "),
+        row.download_url.to_string(),
+        row.cover_url.to_string(),
+        row.total_uv.to_string(),
+        row.like_count.to_string(),
+        row.download_status.map(status_label).unwrap_or_default().to_string(),
+        row.file_path.unwrap_or_default().to_string(),
+    ]
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// 带 UTF-8 BOM，Excel 直接双击打开中文不乱码
+fn to_csv(rows: &[ExportRow]) -> Vec<u8> {
+    let mut out = String::from("\u{feff}");
+    out.push_str(&HEADERS.join(","));
+    out.push_str("\r\n");
+    for row in rows {
+        let cells = text_cells(row);
+        let line: Vec<String> = cells.iter().map(|cell| csv_field(cell)).collect();
+        out.push_str(&line.join(","));
+        out.push_str("\r\n");
+    }
+    out.into_bytes()
+}
+
+fn to_xlsx(rows: &[ExportRow]) -> Result<Vec<u8>, String> {
+    let xlsx_err = |e: rust_xlsxwriter::XlsxError| format!("生成 XLSX 失败: {e}");
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("教材清单").map_err(xlsx_err)?;
+
+    let bold = Format::new().set_bold();
+    for (col, header) in HEADERS.iter().enumerate() {
+        sheet
+            .write_string_with_format(0, col as u16, *header, &bold)
+            .map_err(xlsx_err)?;
+    }
+    for (i, row) in rows.iter().enumerate() {
+        let r = i as u32 + 1;
+        for (col, cell) in text_cells(row).iter().enumerate() {
+            match col {
+                5 => sheet.write_number(r, col as u16, row.total_uv as f64),
+                6 => sheet.write_number(r, col as u16, row.like_count as f64),
+                _ => sheet.write_string(r, col as u16, cell),
+            }
+            .map_err(xlsx_err)?;
+        }
+    }
+    sheet.set_freeze_panes(1, 0).map_err(xlsx_err)?;
+    sheet.autofit();
+    workbook.save_to_buffer().map_err(xlsx_err)
+}
+
+/// 导出教材清单，返回写入的行数。downloads 以 download_url 为键
+#[command]
+pub async fn export_textbooks(
+    textbooks: Vec<Textbook>,
+    downloads: Option<HashMap<String, LocalDownload>>,
+    path: String,
+) -> Result<usize, String> {
+    let path = Path::new(&path);
+    let format = ExportFormat::from_path(path)?;
+    let downloads = downloads.unwrap_or_default();
+    let rows = rows(&textbooks, &downloads);
+
+    let bytes = match format {
+        ExportFormat::Csv => to_csv(&rows),
+        ExportFormat::Json => {
+            serde_json::to_vec_pretty(&rows).map_err(|e| format!("生成 JSON 失败: {e}"))?
+        }
+        ExportFormat::Xlsx => to_xlsx(&rows)?,
+    };
+    fs::write(path, bytes)
+        .await
+        .map_err(|e| format!("写入导出文件失败: {e}"))?;
+    log::info!("已导出 {} 本教材: {}", rows.len(), path.display());
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn textbook(id: &str, title: &str) -> Textbook {
+        Textbook {
+            id: id.to_string(),
+            cover_url: String::new(),
+            title: title.to_string(),
+            total_uv: 12,
+            like_count: 3,
+            download_url: format!("https://d/{id}.pdf"),
+            labels: vec!["初中".to_string(), "物理".to_string()],
+            save_path: "初中/物理".to_string(),
+        }
+    }
+
+    #[test]
+    fn writes_csv_with_quoting_and_status() {
+        let books = vec![textbook("a", "物理, 八年级"), textbook("b", "说\"明\"")];
+        let downloads = HashMap::from([(
+            "https://d/a.pdf".to_string(),
+            LocalDownload {
+                status: "completed".to_string(),
+                file_path: "/x/a.pdf".to_string(),
+            },
+        )]);
+        let csv = String::from_utf8(to_csv(&rows(&books, &downloads))).unwrap();
+        let lines: Vec<&str> = csv.trim_start_matches('\u{feff}').lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            "a,\"物理, 八年级\",初中 / 物理,https://d/a.pdf,,12,3,已下载,/x/a.pdf"
+        );
+        assert_eq!(lines[2], "b,\"说\"\"明\"\"\",初中 / 物理,https://d/b.pdf,,12,3,,");
+
+        let xlsx = to_xlsx(&rows(&books, &downloads)).unwrap();
+        assert!(xlsx.starts_with(b"PK"));
+        assert_eq!(
+            ExportFormat::from_path(Path::new("list.XLSX")),
+            Ok(ExportFormat::Xlsx)
+        );
+        assert!(ExportFormat::from_path(Path::new("list.txt")).is_err());
+    }
+}
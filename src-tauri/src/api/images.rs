@@ -0,0 +1,222 @@
+// 图片自定义协议 kgimg：封面与课程配图不再转 base64 走 IPC，webview 直接按地址加载。
+//   kgimg://localhost/cover/{book_id}   教材封面（详情 thumbnail_1 优先，其次目录字段选出的地址）
+//   kgimg://localhost/image/{原图地址}   任意公开图片（课程封面等）
+// 前端用 convertFileSrc(path, 'kgimg') 生成地址（Windows 上是 http://kgimg.localhost/…），
+// 整段路径会被百分号编码，这里先解码再拆分。
+// 图片落盘缓存在 cache/images/，超出总大小按修改时间淘汰；同时拉取的数量有上限，
+// 一屏几百张封面不会同时打到 CDN。
+
+use super::books;
+use crate::{http, storage};
+use md5::{Digest, Md5};
+use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tauri::http::{Request, Response, StatusCode, header};
+use tokio::fs;
+use tokio::sync::Semaphore;
+
+pub const SCHEME: &str = "kgimg";
+
+const IMAGE_DIR: &str = "images";
+const MAX_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+const MAX_CACHE_FILES: usize = 20_000;
+// 每写入这么多张检查一次缓存目录大小
+const PRUNE_EVERY: usize = 64;
+// 同时向 CDN 拉取的图片数
+const FETCH_CONCURRENCY: usize = 6;
+// 单张图片的拉取时限：共享客户端只设了连接超时，卡住的响应会一直占着名额
+const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+static FETCH_SLOTS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(FETCH_CONCURRENCY));
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq)]
+enum ImageRequest {
+    Cover(String),
+    Image(String),
+}
+
+fn parse_request(path: &str) -> Option<ImageRequest> {
+    let decoded = percent_decode_str(path.trim_start_matches('/'))
+        .decode_utf8()
+        .ok()?;
+    let (kind, rest) = decoded.split_once('/')?;
+    match kind {
+        "cover" if !rest.is_empty() && !rest.contains('/') => {
+            Some(ImageRequest::Cover(rest.to_string()))
+        }
+        "image" if rest.starts_with("https://") || rest.starts_with("http://") => {
+            Some(ImageRequest::Image(rest.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn cache_name(key: &str) -> String {
+    format!("{IMAGE_DIR}/{}", hex::encode(Md5::digest(key.as_bytes())))
+}
+
+// 按文件头判断图片类型，CDN 返回的 Content-Type 不可靠
+fn content_type(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'G', b'I', b'F', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+async fn read_cached(name: &str) -> Option<Vec<u8>> {
+    let path = storage::cache_path(name)?;
+    let bytes = fs::read(&path).await.ok()?;
+    tokio::task::spawn_blocking(move || storage::touch(&path));
+    Some(bytes)
+}
+
+async fn write_cached(name: &str, bytes: &[u8]) {
+    let Some(path) = storage::cache_path(name) else {
+        return;
+    };
+    let Some(dir) = path.parent().map(|p| p.to_path_buf()) else {
+        return;
+    };
+    let tmp_path = path.with_extension("tmp");
+    let written = async {
+        fs::create_dir_all(&dir).await?;
+        fs::write(&tmp_path, bytes).await?;
+        fs::rename(&tmp_path, &path).await
+    }
+    .await;
+    if let Err(e) = written {
+        log::debug!("写入图片缓存失败: {e}");
+        return;
+    }
+    if (WRITES.fetch_add(1, Ordering::Relaxed) + 1) % PRUNE_EVERY == 0 {
+        tokio::task::spawn_blocking(move || {
+            storage::prune_dir(&dir, MAX_CACHE_FILES, MAX_CACHE_BYTES);
+        });
+    }
+}
+
+async fn candidates(request: &ImageRequest) -> Vec<String> {
+    match request {
+        ImageRequest::Image(url) => vec![url.clone()],
+        ImageRequest::Cover(book_id) => {
+            let mut urls = Vec::new();
+            urls.extend(books::resolve_cover_url(book_id).await);
+            urls.extend(books::catalog_cover_url(book_id).await);
+            urls.dedup();
+            urls
+        }
+    }
+}
+
+fn cache_key(request: &ImageRequest) -> String {
+    match request {
+        ImageRequest::Cover(id) => format!("cover:{id}"),
+        ImageRequest::Image(url) => format!("image:{url}"),
+    }
+}
+
+/// 教材重新发布后封面可能也换了，删掉缓存的旧封面，下次显示时重新拉取
+pub async fn forget_cover(book_id: &str) {
+    let name = cache_name(&cache_key(&ImageRequest::Cover(book_id.to_string())));
+    if let Some(path) = storage::cache_path(&name) {
+        let _ = fs::remove_file(path).await;
+    }
+}
+
+async fn load(request: &ImageRequest) -> Result<Vec<u8>, String> {
+    let name = cache_name(&cache_key(request));
+    if let Some(bytes) = read_cached(&name).await {
+        return Ok(bytes);
+    }
+
+    let _slot = FETCH_SLOTS
+        .acquire()
+        .await
+        .map_err(|_| "图片服务已关闭".to_string())?;
+    // 排队期间可能已有同一张图拉取完成
+    if let Some(bytes) = read_cached(&name).await {
+        return Ok(bytes);
+    }
+    let mut last_error = "无可用图片地址".to_string();
+    let urls = tokio::time::timeout(FETCH_TIMEOUT, candidates(request))
+        .await
+        .map_err(|_| "获取封面地址超时".to_string())?;
+    for url in urls {
+        let fetched = tokio::time::timeout(FETCH_TIMEOUT, http::get_bytes(&url))
+            .await
+            .unwrap_or_else(|_| Err(format!("请求超时 {url}")));
+        match fetched {
+            Ok(bytes) => {
+                write_cached(&name, &bytes).await;
+                return Ok(bytes.to_vec());
+            }
+            Err(e) => {
+                log::debug!("图片获取失败，尝试下一个地址: {e}");
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+fn respond(status: StatusCode, content_type: &str, body: Vec<u8>) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "max-age=86400")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(body)
+        .unwrap_or_default()
+}
+
+/// kgimg 协议的处理入口（在 tauri::Builder 上注册为异步协议）
+pub fn handle(request: Request<Vec<u8>>, responder: tauri::UriSchemeResponder) {
+    let path = request.uri().path().to_string();
+    tauri::async_runtime::spawn(async move {
+        let response = match parse_request(&path) {
+            None => respond(
+                StatusCode::NOT_FOUND,
+                "text/plain; charset=utf-8",
+                b"not found".to_vec(),
+            ),
+            Some(image) => match load(&image).await {
+                Ok(bytes) => respond(StatusCode::OK, content_type(&bytes), bytes),
+                Err(e) => {
+                    log::warn!("图片加载失败 {image:?}: {e}");
+                    respond(
+                        StatusCode::BAD_GATEWAY,
+                        "text/plain; charset=utf-8",
+                        e.into_bytes(),
+                    )
+                }
+            },
+        };
+        responder.respond(response);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_encoded_paths() {
+        assert_eq!(
+            parse_request("/cover%2Fabc-123"),
+            Some(ImageRequest::Cover("abc-123".to_string()))
+        );
+        assert_eq!(
+            parse_request("/image%2Fhttps%3A%2F%2Fcdn%2Fa%2Fb.jpg"),
+            Some(ImageRequest::Image("https://cdn/a/b.jpg".to_string()))
+        );
+        assert_eq!(parse_request("/image/file:///etc/passwd"), None);
+        assert_eq!(parse_request("/cover/a/b"), None);
+        assert_eq!(content_type(b"\x89PNG\r\n"), "image/png");
+        assert_eq!(content_type(b"\xff\xd8\xff"), "image/jpeg");
+    }
+}
@@ -0,0 +1,331 @@
+// 同步课堂目录：按教材的章节树浏览课时（national_lesson）。
+// - 章节树：national_lesson/trees/{教材 id}.json，节点 id/title/child_nodes
+// - 课时清单：national_lesson/teachingmaterials/{教材 id}/resources/parts.json 列出分片地址，
+//   每片是课时数组，chapter_paths 形如 "根节点/…/章节 id"
+// 同步课堂与教材目录共用教材 id。每个课时落到 classActivity 页面路由上，
+// 取资源时走与粘贴链接相同的解析，整单元入队不必逐个打开网页。
+// 课时会陆续上新，与教材书目的 module_version 无关：课时清单不走详情缓存的版本作废，
+// 而是在内存里按教材缓存一段时间，过期后重新拉取；拉不到时退回旧清单或磁盘缓存。
+
+use super::{courses, detail_cache};
+use crate::models::CourseParseResult;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::command;
+use url::Url;
+
+const TREE_URL: &str = "https://s-file-1.ykt.cbern.com.cn/zxx/ndrv2/national_lesson/trees";
+const PARTS_URL: &str =
+    "https://s-file-1.ykt.cbern.com.cn/zxx/ndrs/national_lesson/teachingmaterials";
+const PAGE_URL: &str = "https://basic.smartedu.cn/syncClassroom/classActivity";
+// 整单元解析时并发请求的课时详情数
+const PARSE_CONCURRENCY: usize = 4;
+// 课时清单的重新拉取间隔
+const LESSONS_TTL: Duration = Duration::from_secs(30 * 60);
+
+// 章节（或整本书）下没有课时：不是故障，配套资源汇总据此与获取失败区分
+pub(super) const NO_LESSONS: &str = "该章节下没有课时";
+
+// 教材 id → (拉取时刻, 课时清单)
+type LessonsCache = HashMap<String, (Instant, Arc<Vec<RawLesson>>)>;
+static LESSONS_CACHE: Lazy<Mutex<LessonsCache>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LessonEntry {
+    pub id: String,
+    pub title: String,
+    // 平台课时页地址，可直接交给 parse_course_url
+    pub page_url: String,
+    pub detail_url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LessonNode {
+    pub id: String,
+    pub title: String,
+    pub lessons: Vec<LessonEntry>,
+    pub children: Vec<LessonNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawNode {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    child_nodes: Vec<RawNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawLesson {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    chapter_paths: Vec<String>,
+}
+
+fn lesson_entry(book_id: &str, chapter_id: &str, lesson: &RawLesson) -> Option<LessonEntry> {
+    let page_url = Url::parse_with_params(
+        PAGE_URL,
+        [
+            ("activityId", lesson.id.as_str()),
+            ("chapterId", chapter_id),
+            ("teachingmaterialId", book_id),
+        ],
+    )
+    .ok()?;
+    let detail_url = courses::detail_url_of(&page_url)?;
+    Some(LessonEntry {
+        id: lesson.id.clone(),
+        title: lesson.title.clone(),
+        page_url: page_url.to_string(),
+        detail_url,
+    })
+}
+
+fn build_node(
+    raw: &RawNode,
+    book_id: &str,
+    by_chapter: &mut HashMap<&str, Vec<&RawLesson>>,
+) -> LessonNode {
+    let lessons = by_chapter
+        .remove(raw.id.as_str())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|lesson| lesson_entry(book_id, &raw.id, lesson))
+        .collect();
+    LessonNode {
+        id: raw.id.clone(),
+        title: raw.title.clone(),
+        lessons,
+        children: raw
+            .child_nodes
+            .iter()
+            .map(|child| build_node(child, book_id, by_chapter))
+            .collect(),
+    }
+}
+
+// 课时按 chapter_paths 的末级 id 挂到章节上；章节树里找不到的归入「其他课时」
+fn build_tree(book_id: &str, roots: &[RawNode], lessons: &[RawLesson]) -> Vec<LessonNode> {
+    let mut by_chapter: HashMap<&str, Vec<&RawLesson>> = HashMap::new();
+    for lesson in lessons.iter().filter(|lesson| !lesson.id.is_empty()) {
+        let chapter = lesson
+            .chapter_paths
+            .first()
+            .and_then(|path| path.rsplit('/').find(|id| !id.is_empty()))
+            .unwrap_or("");
+        by_chapter.entry(chapter).or_default().push(lesson);
+    }
+
+    let mut tree: Vec<LessonNode> = roots
+        .iter()
+        .map(|root| build_node(root, book_id, &mut by_chapter))
+        .collect();
+
+    let mut rest: Vec<&RawLesson> = by_chapter.into_values().flatten().collect();
+    if !rest.is_empty() {
+        rest.sort_by(|a, b| a.title.cmp(&b.title));
+        tree.push(LessonNode {
+            id: String::new(),
+            title: "其他课时".to_string(),
+            lessons: rest
+                .into_iter()
+                .filter_map(|lesson| lesson_entry(book_id, "", lesson))
+                .collect(),
+            children: Vec::new(),
+        });
+    }
+    tree
+}
+
+fn find_node<'a>(nodes: &'a [LessonNode], node_id: &str) -> Option<&'a LessonNode> {
+    nodes.iter().find_map(|node| {
+        if node.id == node_id {
+            Some(node)
+        } else {
+            find_node(&node.children, node_id)
+        }
+    })
+}
+
+fn collect_lessons<'a>(node: &'a LessonNode, out: &mut Vec<&'a LessonEntry>) {
+    out.extend(&node.lessons);
+    for child in &node.children {
+        collect_lessons(child, out);
+    }
+}
+
+// 教材 id 直接拼进接口路径，限定字符集
+pub(super) fn is_safe_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// fresh 为 true 时绕过详情缓存（同时刷新磁盘上的副本，供断网时退回）
+async fn fetch_lessons(book_id: &str, fresh: bool) -> Result<Vec<RawLesson>, String> {
+    let get = |url: String| async move {
+        if fresh {
+            detail_cache::fetch_fresh(&url).await
+        } else {
+            detail_cache::get(&url).await
+        }
+    };
+    let parts = get(format!("{PARTS_URL}/{book_id}/resources/parts.json")).await?;
+    let urls: Vec<String> =
+        Vec::deserialize(parts.as_ref()).map_err(|e| format!("课时清单格式异常: {e}"))?;
+    let mut lessons = Vec::new();
+    for url in urls {
+        let part = get(url).await?;
+        let items: Vec<RawLesson> =
+            Vec::deserialize(part.as_ref()).map_err(|e| format!("课时清单格式异常: {e}"))?;
+        lessons.extend(items);
+    }
+    Ok(lessons)
+}
+
+async fn load_lessons(book_id: &str) -> Result<Arc<Vec<RawLesson>>, String> {
+    let cached = LESSONS_CACHE.lock().unwrap().get(book_id).cloned();
+    if let Some((fetched_at, lessons)) = &cached {
+        if fetched_at.elapsed() < LESSONS_TTL {
+            return Ok(Arc::clone(lessons));
+        }
+    }
+    let lessons = match fetch_lessons(book_id, true).await {
+        Ok(lessons) => Arc::new(lessons),
+        Err(e) => {
+            if let Some((_, lessons)) = cached {
+                log::warn!("课时清单获取失败，使用内存中的旧清单: {e}");
+                return Ok(lessons);
+            }
+            log::warn!("课时清单获取失败，尝试本地缓存: {e}");
+            return fetch_lessons(book_id, false).await.map(Arc::new).map_err(|_| e);
+        }
+    };
+    LESSONS_CACHE
+        .lock()
+        .unwrap()
+        .insert(book_id.to_string(), (Instant::now(), Arc::clone(&lessons)));
+    Ok(lessons)
+}
+
+async fn load_tree(book_id: &str) -> Result<Vec<LessonNode>, String> {
+    if !is_safe_id(book_id) {
+        return Err(format!("无效的教材 id: {book_id}"));
+    }
+    let roots = detail_cache::get(&format!("{TREE_URL}/{book_id}.json"))
+        .await
+        .map_err(|e| format!("获取章节目录失败: {e}"))?;
+    let roots: Vec<RawNode> =
+        Vec::deserialize(roots.as_ref()).map_err(|e| format!("章节目录格式异常: {e}"))?;
+    let lessons = load_lessons(book_id)
+        .await
+        .map_err(|e| format!("获取课时清单失败: {e}"))?;
+    Ok(build_tree(book_id, &roots, &lessons))
+}
+
+/// 教材的同步课堂章节树，每个节点带本章节下的课时
+#[command]
+pub async fn fetch_lesson_tree(book_id: String) -> Result<Vec<LessonNode>, String> {
+    load_tree(&book_id).await
+}
+
+/// 解析某章节（含子章节）下所有课时的资源，按目录顺序返回；
+/// node_id 为空时解析整本教材。单个课时解析失败只记日志
+#[command]
+pub async fn parse_lesson_unit(
+    book_id: String,
+    node_id: Option<String>,
+) -> Result<Vec<CourseParseResult>, String> {
+    parse_lessons(&book_id, node_id.as_deref()).await
+}
+
+pub(super) async fn parse_lessons(
+    book_id: &str,
+    node_id: Option<&str>,
+) -> Result<Vec<CourseParseResult>, String> {
+    let tree = load_tree(book_id).await?;
+    let mut lessons = Vec::new();
+    match node_id.filter(|id| !id.is_empty()) {
+        Some(id) => {
+            let node = find_node(&tree, id).ok_or_else(|| format!("找不到章节 {id}"))?;
+            collect_lessons(node, &mut lessons);
+        }
+        None => tree
+            .iter()
+            .for_each(|node| collect_lessons(node, &mut lessons)),
+    }
+    if lessons.is_empty() {
+        return Err(NO_LESSONS.to_string());
+    }
+
+    let total = lessons.len();
+    let lessons: Vec<LessonEntry> = lessons.into_iter().cloned().collect();
+    let results: Vec<CourseParseResult> = futures_util::stream::iter(lessons)
+        .map(|lesson| async move {
+            let parsed = Url::parse(&lesson.page_url).ok()?;
+            match courses::parse_page(&parsed).await {
+                Ok(result) => Some(result),
+                Err(e) => {
+                    log::warn!("课时解析失败 {}: {e}", lesson.title);
+                    None
+                }
+            }
+        })
+        .buffered(PARSE_CONCURRENCY)
+        .filter_map(|result| async move { result })
+        .collect()
+        .await;
+    if results.is_empty() {
+        return Err("该章节下的课时都未能解析出资源".to_string());
+    }
+    log::info!("章节解析完成：{}/{} 个课时", results.len(), total);
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn attaches_lessons_to_chapters_and_routes() {
+        let roots: Vec<RawNode> = serde_json::from_value(json!([{
+            "id": "u1", "title": "第一单元",
+            "child_nodes": [{ "id": "c1", "title": "1 春", "child_nodes": [] }]
+        }]))
+        .unwrap();
+        let lessons: Vec<RawLesson> = serde_json::from_value(json!([
+            { "id": "l1", "title": "春（第一课时）", "chapter_paths": ["root/u1/c1"] },
+            { "id": "l2", "title": "单元导读", "chapter_paths": ["root/u1"] },
+            { "id": "l3", "title": "拓展", "chapter_paths": ["root/gone"] },
+        ]))
+        .unwrap();
+        let tree = build_tree("book", &roots, &lessons);
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].lessons[0].id, "l2");
+        let chapter = find_node(&tree, "c1").unwrap();
+        assert_eq!(chapter.lessons[0].title, "春（第一课时）");
+        assert_eq!(
+            chapter.lessons[0].detail_url,
+            "https://s-file-2.ykt.cbern.com.cn/zxx/ndrv2/national_lesson/resources/details/l1.json"
+        );
+        assert_eq!(tree[1].title, "其他课时");
+        assert_eq!(tree[1].lessons[0].id, "l3");
+
+        let mut all = Vec::new();
+        collect_lessons(&tree[0], &mut all);
+        assert_eq!(all.len(), 2);
+        assert!(!is_safe_id("../x"));
+    }
+}
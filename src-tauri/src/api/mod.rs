@@ -1,129 +1,241 @@
 pub mod books;
+pub mod changes;
+pub mod companions;
 pub mod courses;
+pub mod detail_cache;
+pub mod export;
+pub mod images;
+pub mod lessons;
+pub mod search;
+pub mod tag_index;
 pub mod tags;
 
-use crate::http;
-use crate::models::{DropdownOption, FilterOptionsArgs, Textbook};
-use base64::{Engine, engine::general_purpose::STANDARD};
-use tags::{HIGH_SCHOOL, SPECIAL_EDUCATION};
-use tauri::command;
-
-#[command]
-pub async fn fetch_textbooks(
-    category_id: String,
-    subject_id: String,
-    version_id: String,
-    grade_id: String,
-    year_id: Option<String>,
-) -> Result<Vec<Textbook>, String> {
-    let tree = tags::fetch_tag_tree().await?;
-
-    let category = tags::find_category(&tree, &category_id);
-    let is_special = category.is_some_and(|c| c.tag_name == SPECIAL_EDUCATION);
-    let is_high_school = category.is_some_and(|c| c.tag_name == HIGH_SCHOOL);
-
-    // 高中没有年级层级；特殊教育多一层「年份」
-    let is_valid = !category_id.is_empty()
-        && !subject_id.is_empty()
-        && !version_id.is_empty()
-        && (is_high_school || !grade_id.is_empty())
-        && (!is_special || year_id.is_some());
-    if !is_valid {
-        return Ok(vec![]);
-    }
-
-    let mut required = vec![category_id, subject_id, version_id];
-    if !grade_id.is_empty() || is_special {
-        required.push(grade_id);
-    }
-    if is_special {
-        if let Some(year) = year_id {
-            required.push(year);
-        }
+use crate::models::{
+    DropdownOption, DropdownOptions, FilterOptionsArgs, TagChild, TagChildren, TagNode,
+    TextbookList,
+};
+use changes::CatalogChanges;
+use tauri::{Emitter, command};
+
+// 取书目；本次发现了新版本且有变化时广播 catalog-updated（载荷即变化明细）
+async fn load_catalog(app_handle: &tauri::AppHandle) -> Result<books::Catalog, String> {
+    let catalog = books::get_raw_books().await?;
+    if let Some(changes) = catalog.changes.as_deref() {
+        let _ = app_handle.emit("catalog-updated", changes);
     }
+    Ok(catalog)
+}
 
-    let raw_books = books::get_raw_books().await?;
-    let filtered = books::filter_books(&raw_books, &required);
+// 按任意深度的 tag id 路径取书目：路径须是某条 tag_path 里的连续片段（前缀即可，不要求到叶子）
+async fn textbooks_at(
+    app_handle: &tauri::AppHandle,
+    tree: &tags::TagTree,
+    path: &[String],
+) -> Result<TextbookList, String> {
+    let catalog = load_catalog(app_handle).await?;
+    let filtered = catalog.index.filter(&catalog.books, path);
     let ids: Vec<String> = filtered.iter().map(|book| book.id.clone()).collect();
-    let stats = books::fetch_statistics(&ids).await;
+    // 离线时统计接口同样不可达，不必再等它超时
+    let stats = if catalog.offline {
+        Default::default()
+    } else {
+        books::fetch_statistics(&ids).await
+    };
 
-    Ok(books::to_textbooks(filtered, &stats))
+    let names = tags::label_map(&tree.tree);
+    Ok(TextbookList {
+        textbooks: books::to_textbooks(filtered, &stats, &names, path),
+        offline: catalog.offline || tree.offline,
+    })
 }
 
-#[command]
-pub async fn fetch_filter_options(args: FilterOptionsArgs) -> Result<Vec<DropdownOption>, String> {
-    let tree = tags::fetch_tag_tree().await?;
+// 给下一级节点补上书目数：条件为路径上各级 id 再加上该节点
+fn with_counts(nodes: &mut [TagNode], path: &[String], catalog: &books::Catalog) {
+    let mut required = path.to_vec();
+    for node in nodes {
+        required.push(node.value.clone());
+        node.count = Some(catalog.index.count(&catalog.books, &required));
+        required.pop();
+    }
+}
 
-    let Some(cat) = args.category_id.as_deref() else {
-        return Ok(vec![]);
+// 路径下一级的节点；路径为空时返回顶层分类，路径不在标签树里时为 None
+fn child_nodes(tree: &[TagChild], path: &[String]) -> Option<(Vec<String>, Vec<TagNode>)> {
+    let ids: Vec<&str> = path.iter().map(String::as_str).collect();
+    let (labels, children): (Vec<String>, Vec<&TagChild>) = if ids.is_empty() {
+        (Vec::new(), tree.iter().collect())
+    } else {
+        let nodes = tags::nodes_on_path(tree, &ids)?;
+        let labels = nodes.iter().map(|node| node.tag_name.clone()).collect();
+        (labels, tags::children_of(nodes.last()?).collect())
     };
+    let children = children
+        .into_iter()
+        .map(|child| TagNode {
+            value: child.tag_id.clone(),
+            label: child.tag_name.clone(),
+            count: None,
+            is_leaf: tags::children_of(child).next().is_none(),
+        })
+        .collect();
+    Some((labels, children))
+}
 
-    let options = match (
-        args.subject_id.as_deref(),
-        args.version_id.as_deref(),
-        args.grade_id.as_deref(),
-    ) {
-        (None, ..) => tags::find_path(&tree, &[cat]).map(tags::child_options),
-        (Some(subj), None, _) => tags::find_path(&tree, &[cat, subj]).map(tags::child_options),
-        (Some(subj), Some(ver), None) => match tags::find_path(&tree, &[cat, subj]) {
-            // 高中层级下没有年级
-            Some(subject) if subject.tag_name == HIGH_SCHOOL => None,
-            Some(_) => tags::find_path(&tree, &[cat, subj, ver]).map(tags::child_options),
-            None => None,
-        },
-        (Some(subj), Some(ver), Some(grade)) => {
-            tags::find_path(&tree, &[cat, subj, ver, grade]).map(tags::child_options)
+async fn tag_children(
+    app_handle: &tauri::AppHandle,
+    tree: &tags::TagTree,
+    path: &[String],
+) -> Result<TagChildren, String> {
+    let (labels, mut children) = child_nodes(&tree.tree, path)
+        .ok_or_else(|| format!("标签路径不存在: {}", path.join("/")))?;
+    let mut offline = tree.offline;
+    if !children.is_empty() {
+        // 计数用内存里已有的书目，每切换一次筛选项就确认一次版本太慢（断网时还要等连接超时）；
+        // 版本更新交给取书目与 catalog-updated。还没加载过时才取一次，取不到仍返回节点，只是没有数字
+        let catalog = match books::loaded_catalog().await {
+            Some(catalog) => Ok(catalog),
+            None => load_catalog(app_handle).await,
+        };
+        match catalog {
+            Ok(catalog) => {
+                with_counts(&mut children, path, &catalog);
+                offline |= catalog.offline;
+            }
+            Err(e) => log::warn!("书目不可用，筛选项不显示数量: {e}"),
         }
-    };
-
-    Ok(options.unwrap_or_default())
+    }
+    Ok(TagChildren {
+        is_leaf: !path.is_empty() && children.is_empty(),
+        labels,
+        children,
+        offline,
+    })
 }
 
+/// 通用标签导航：给出任意深度的 tag id 路径，返回该节点的下一级与是否为叶子
 #[command]
-pub async fn fetch_textbook_categories() -> Result<Vec<DropdownOption>, String> {
+pub async fn fetch_tag_children(
+    app_handle: tauri::AppHandle,
+    path: Vec<String>,
+) -> Result<TagChildren, String> {
     let tree = tags::fetch_tag_tree().await?;
-    Ok(tree
-        .iter()
-        .map(|c| DropdownOption {
-            value: c.tag_id.clone(),
-            label: c.tag_name.clone(),
-        })
-        .collect())
+    tag_children(&app_handle, &tree, &path).await
 }
 
+/// 通用书目筛选：路径里各级 tag id 按顺序连续出现的书目，路径可停在任意一级
 #[command]
-pub async fn clear_tch_material_tag_cache() -> Result<(), String> {
-    tags::clear_cache();
-    books::clear_cache().await;
-    log::info!("已清理标签与书目缓存");
-    Ok(())
+pub async fn fetch_textbooks_by_path(
+    app_handle: tauri::AppHandle,
+    path: Vec<String>,
+) -> Result<TextbookList, String> {
+    let path: Vec<String> = path.into_iter().filter(|id| !id.is_empty()).collect();
+    if path.is_empty() {
+        return Err("请至少选择一级分类".to_string());
+    }
+    let tree = tags::fetch_tag_tree().await?;
+    textbooks_at(&app_handle, &tree, &path).await
 }
 
-// 封面直连有防盗链，由后端代理拉取并转 base64 给前端。
-// 优先用详情解析出的、与源 PDF 同批的转码首页图；详情不可用时退回目录字段选出的地址
+// 以下是按固定层级传参的旧命令，转调上面的通用实现
+
+/// 分类/学科/版本/年级（特殊教育还有年份）选到叶子时返回书目，未选完时返回空列表
 #[command]
-pub async fn fetch_cover(book_id: String, fallback_url: String) -> Result<String, String> {
-    if let Some(url) = books::resolve_cover_url(&book_id).await {
-        match http::get_bytes(&url).await {
-            Ok(bytes) => return Ok(STANDARD.encode(&bytes)),
-            Err(e) => log::warn!("详情封面获取失败，使用目录封面: {e}"),
-        }
+pub async fn fetch_textbooks(
+    app_handle: tauri::AppHandle,
+    category_id: String,
+    subject_id: String,
+    version_id: String,
+    grade_id: String,
+    year_id: Option<String>,
+) -> Result<TextbookList, String> {
+    let tree = tags::fetch_tag_tree().await?;
+    let path: Vec<String> = [
+        Some(category_id),
+        Some(subject_id),
+        Some(version_id),
+        Some(grade_id),
+        year_id,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|id| !id.is_empty())
+    .collect();
+
+    // 有的分支没有年级（高中），有的多一层年份（特殊教育）：以标签树里是否到了叶子为准
+    let ids: Vec<&str> = path.iter().map(String::as_str).collect();
+    let is_leaf = tags::find_path(&tree.tree, &ids)
+        .is_some_and(|node| tags::children_of(node).next().is_none());
+    if !is_leaf {
+        return Ok(TextbookList {
+            textbooks: vec![],
+            offline: tree.offline,
+        });
     }
+    textbooks_at(&app_handle, &tree, &path).await
+}
 
-    if fallback_url.is_empty() {
-        return Err("无可用封面".to_string());
+#[command]
+pub async fn fetch_filter_options(
+    app_handle: tauri::AppHandle,
+    args: FilterOptionsArgs,
+) -> Result<DropdownOptions, String> {
+    if args.category_id.is_none() {
+        return Ok(DropdownOptions {
+            options: vec![],
+            offline: false,
+        });
     }
-    let bytes = http::get_bytes(&fallback_url).await?;
-    Ok(STANDARD.encode(&bytes))
+    let path: Vec<String> = [
+        args.category_id,
+        args.subject_id,
+        args.version_id,
+        args.grade_id,
+    ]
+    .into_iter()
+    .map_while(|id| id)
+    .collect();
+
+    let tree = tags::fetch_tag_tree().await?;
+    // 路径不在标签树里（如上级切换后残留的下级 id）时没有选项
+    let (children, offline) = match tag_children(&app_handle, &tree, &path).await {
+        Ok(children) => (children.children, children.offline),
+        Err(_) => (Vec::new(), tree.offline),
+    };
+    Ok(DropdownOptions {
+        options: children.into_iter().map(DropdownOption::from).collect(),
+        offline,
+    })
+}
+
+#[command]
+pub async fn fetch_textbook_categories() -> Result<DropdownOptions, String> {
+    let tree = tags::fetch_tag_tree().await?;
+    Ok(DropdownOptions {
+        options: child_nodes(&tree.tree, &[])
+            .map(|(_, children)| children)
+            .unwrap_or_default()
+            .into_iter()
+            .map(DropdownOption::from)
+            .collect(),
+        offline: tree.offline,
+    })
 }
 
-// 通用图片代理：拉取任意公开图片转 base64，供前端 <img> 直接用。
-// 课程封面在公开桶，无需鉴权，但和教材封面一样在 webview 里直连不可靠，故走后端。
+/// 「有什么更新」：先检查一次书目版本，再返回最近一次检测到的变化（没有记录时为 null）
 #[command]
-pub async fn fetch_image(url: String) -> Result<String, String> {
-    if url.is_empty() {
-        return Err("无图片地址".to_string());
+pub async fn fetch_catalog_changes(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<CatalogChanges>, String> {
+    if let Err(e) = load_catalog(&app_handle).await {
+        log::warn!("检查书目更新失败: {e}");
     }
-    let bytes = http::get_bytes(&url).await?;
-    Ok(STANDARD.encode(&bytes))
+    Ok(changes::latest().await.map(|changes| changes.as_ref().clone()))
+}
+
+#[command]
+pub async fn clear_tch_material_tag_cache() -> Result<(), String> {
+    tags::clear_cache().await;
+    books::clear_cache().await;
+    log::info!("已清理标签与书目缓存");
+    Ok(())
 }
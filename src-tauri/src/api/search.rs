@@ -0,0 +1,247 @@
+// 全书目搜索：在教材标题与分类名称（由 tag_paths 还原）上做子串、拼音全拼/首字母
+// 与模糊（子序列）匹配。查询按空白拆成多个词，每个词都要命中，得分累加后排序。
+// 索引随书目与标签树一起缓存，二者未变时重复搜索不必重建。
+
+use super::{books, tags};
+use crate::models::{RawBook, TagChild, TextbookList};
+use once_cell::sync::Lazy;
+use pinyin::ToPinyin;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::command;
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+// 单个查询词的命中得分，越精确越高
+const SCORE_TITLE: u32 = 6;
+const SCORE_LABEL: u32 = 5;
+const SCORE_INITIALS: u32 = 4;
+const SCORE_FULL_PINYIN: u32 = 3;
+const SCORE_FUZZY: u32 = 1;
+
+struct SearchDoc {
+    // 以下均为小写
+    title: String,
+    // 标题 + 全部分类名称，以空格分隔
+    text: String,
+    // 拼音全拼与首字母；非汉字的字母数字原样保留，空格保留作分隔
+    full: String,
+    initials: String,
+}
+
+struct SearchIndex {
+    books: Arc<Vec<RawBook>>,
+    tree: Arc<Vec<TagChild>>,
+    docs: Arc<Vec<SearchDoc>>,
+}
+
+static SEARCH_INDEX: Lazy<Mutex<Option<SearchIndex>>> = Lazy::new(|| Mutex::new(None));
+
+fn pinyin_keys(text: &str) -> (String, String) {
+    let mut full = String::with_capacity(text.len() * 2);
+    let mut initials = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let Some(p) = c.to_pinyin() {
+            full.push_str(p.plain());
+            initials.push_str(p.first_letter());
+        } else if c.is_alphanumeric() {
+            full.extend(c.to_lowercase());
+            initials.extend(c.to_lowercase());
+        } else if c.is_whitespace() && !full.ends_with(' ') {
+            full.push(' ');
+            initials.push(' ');
+        }
+    }
+    (full, initials)
+}
+
+fn build_doc(book: &RawBook, names: &HashMap<String, String>) -> SearchDoc {
+    let mut text = book.title.to_lowercase();
+    for label in book.tag_paths.iter().flat_map(|path| tags::resolve_path(names, path)) {
+        text.push(' ');
+        text.push_str(&label.to_lowercase());
+    }
+    let (full, initials) = pinyin_keys(&text);
+    SearchDoc {
+        title: book.title.to_lowercase(),
+        text,
+        full,
+        initials,
+    }
+}
+
+fn build_docs(books: &[RawBook], tree: &[TagChild]) -> Vec<SearchDoc> {
+    let names = tags::label_map(tree);
+    books.iter().map(|book| build_doc(book, &names)).collect()
+}
+
+fn search_docs(books: &Arc<Vec<RawBook>>, tree: &Arc<Vec<TagChild>>) -> Arc<Vec<SearchDoc>> {
+    let mut index = SEARCH_INDEX.lock().unwrap();
+    if let Some(cached) = index.as_ref() {
+        if Arc::ptr_eq(&cached.books, books) && Arc::ptr_eq(&cached.tree, tree) {
+            return Arc::clone(&cached.docs);
+        }
+    }
+    let docs = Arc::new(build_docs(books, tree));
+    *index = Some(SearchIndex {
+        books: Arc::clone(books),
+        tree: Arc::clone(tree),
+        docs: Arc::clone(&docs),
+    });
+    docs
+}
+
+// needle 的字符按顺序出现在 haystack 中（可不连续）
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut rest = haystack.chars();
+    needle.chars().all(|c| rest.any(|h| h == c))
+}
+
+fn token_score(doc: &SearchDoc, token: &str) -> u32 {
+    if doc.title.contains(token) {
+        return SCORE_TITLE;
+    }
+    if doc.text.contains(token) {
+        return SCORE_LABEL;
+    }
+    // 单个字符做模糊匹配几乎处处命中，没有意义
+    let fuzzy = token.chars().count() >= 2;
+    if token.is_ascii() {
+        if doc.initials.contains(token) {
+            SCORE_INITIALS
+        } else if doc.full.contains(token) {
+            SCORE_FULL_PINYIN
+        } else if fuzzy && is_subsequence(token, &doc.initials) {
+            SCORE_FUZZY
+        } else {
+            0
+        }
+    } else if fuzzy && is_subsequence(token, &doc.text) {
+        SCORE_FUZZY
+    } else {
+        0
+    }
+}
+
+/// 返回命中的书目下标，按得分降序；同分时标题短的（更贴近查询）在前
+fn rank(docs: &[SearchDoc], query: &str, limit: usize) -> Vec<usize> {
+    let query = query.to_lowercase();
+    let tokens: Vec<&str> = query.split_whitespace().collect();
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hits: Vec<(u32, usize)> = docs
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, doc)| {
+            let mut total = 0;
+            for token in &tokens {
+                match token_score(doc, token) {
+                    0 => return None,
+                    score => total += score,
+                }
+            }
+            Some((total, idx))
+        })
+        .collect();
+    hits.sort_by(|(sa, a), (sb, b)| {
+        sb.cmp(sa)
+            .then_with(|| docs[*a].title.chars().count().cmp(&docs[*b].title.chars().count()))
+    });
+    hits.into_iter().take(limit).map(|(_, idx)| idx).collect()
+}
+
+/// 全书目搜索，如「八年级 物理 人教」「yw」「bnjwl」
+#[command]
+pub async fn search_textbooks(
+    app_handle: tauri::AppHandle,
+    query: String,
+    limit: Option<usize>,
+) -> Result<TextbookList, String> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let tree = tags::fetch_tag_tree().await?;
+    let catalog = super::load_catalog(&app_handle).await?;
+    let docs = search_docs(&catalog.books, &tree.tree);
+
+    let hits = rank(&docs, &query, limit);
+    let ids: Vec<String> = hits
+        .iter()
+        .map(|&idx| catalog.books[idx].id.clone())
+        .collect();
+    let stats = if catalog.offline {
+        Default::default()
+    } else {
+        books::fetch_statistics(&ids).await
+    };
+
+    let names = tags::label_map(&tree.tree);
+    Ok(TextbookList {
+        textbooks: books::to_textbooks(
+            hits.iter().map(|&idx| &catalog.books[idx]).collect(),
+            &stats,
+            &names,
+            &[],
+        ),
+        offline: catalog.offline || tree.offline,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, title: &str, path: &str) -> RawBook {
+        RawBook {
+            id: id.to_string(),
+            title: title.to_string(),
+            tag_paths: vec![path.to_string()],
+            custom_properties: None,
+        }
+    }
+
+    fn docs() -> Vec<SearchDoc> {
+        let names: HashMap<String, String> = [
+            ("cz", "初中"),
+            ("yw", "语文"),
+            ("wl", "物理"),
+            ("tb", "统编版"),
+            ("rj", "人教版"),
+            ("bs", "北师大版"),
+            ("g8", "八年级"),
+        ]
+        .into_iter()
+        .map(|(id, name)| (id.to_string(), name.to_string()))
+        .collect();
+        [
+            book("a", "语文 八年级上册", "root/cz/yw/tb/g8"),
+            book("b", "物理 八年级上册", "root/cz/wl/rj/g8"),
+            book("c", "物理 八年级上册", "root/cz/wl/bs/g8"),
+        ]
+        .iter()
+        .map(|b| build_doc(b, &names))
+        .collect()
+    }
+
+    #[test]
+    fn matches_labels_and_pinyin() {
+        let docs = docs();
+        assert_eq!(rank(&docs, "八年级 物理 人教", 10), vec![1]);
+        assert_eq!(rank(&docs, "yw", 10), vec![0]);
+        assert_eq!(rank(&docs, "wuli beishida", 10), vec![2]);
+        // 首字母的模糊匹配：八年级物理 → bnjwl
+        assert_eq!(rank(&docs, "BNWL", 10), vec![1, 2]);
+        assert!(rank(&docs, "  ", 10).is_empty());
+    }
+
+    #[test]
+    fn title_hits_rank_above_label_hits() {
+        let mut docs = docs();
+        let names = HashMap::from([("wl".to_string(), "物理".to_string())]);
+        docs.insert(0, build_doc(&book("d", "八年级 实验手册", "root/wl"), &names));
+        // d 只在分类名里含「物理」，排在标题命中的两本之后
+        assert_eq!(rank(&docs, "物理", 10), vec![2, 3, 0]);
+        assert_eq!(rank(&docs, "物理", 1), vec![2]);
+    }
+}
@@ -0,0 +1,113 @@
+// 书目的倒排索引：tag id → 路径里含该 id 的书目下标。书目加载时建好，随书目一起缓存。
+// 筛选时先取条件里最稀有的 id 的倒排表作候选，再逐本校验路径（条件须是同一条
+// tag_path 里的连续片段，多条路径的书不能跨路径凑条件），避免每次扫描全量书目。
+
+use crate::models::RawBook;
+use std::collections::HashMap;
+
+pub struct TagIndex {
+    postings: HashMap<String, Vec<usize>>,
+}
+
+// tag_path 形如 "id1/id2/.../idN"，判断是否包含 required 的连续子序列
+pub(super) fn path_matches(tag_path: &str, required: &[String]) -> bool {
+    let parts: Vec<&str> = tag_path.split('/').collect();
+    parts
+        .windows(required.len())
+        .any(|window| window.iter().zip(required).all(|(part, id)| part == id))
+}
+
+fn book_matches(book: &RawBook, required: &[String]) -> bool {
+    book.tag_paths
+        .iter()
+        .any(|path| path_matches(path, required))
+}
+
+impl TagIndex {
+    pub fn build(books: &[RawBook]) -> Self {
+        let mut postings: HashMap<String, Vec<usize>> = HashMap::new();
+        for (idx, book) in books.iter().enumerate() {
+            for path in &book.tag_paths {
+                for id in path.split('/').filter(|id| !id.is_empty()) {
+                    let list = postings.entry(id.to_string()).or_default();
+                    // 同一本书的多条路径常共用前缀，避免重复记录
+                    if list.last() != Some(&idx) {
+                        list.push(idx);
+                    }
+                }
+            }
+        }
+        Self { postings }
+    }
+
+    // 候选书目：条件里倒排表最短的那个 id；有 id 不在索引里时必然无结果
+    fn candidates(&self, required: &[String]) -> &[usize] {
+        required
+            .iter()
+            .map(|id| self.postings.get(id).map_or(&[][..], Vec::as_slice))
+            .min_by_key(|list| list.len())
+            .unwrap_or(&[])
+    }
+
+    /// 路径里含 required 连续片段的书目，保持书目原顺序
+    pub fn filter<'a>(&self, books: &'a [RawBook], required: &[String]) -> Vec<&'a RawBook> {
+        if required.is_empty() {
+            return books.iter().collect();
+        }
+        self.candidates(required)
+            .iter()
+            .map(|&idx| &books[idx])
+            .filter(|book| book_matches(book, required))
+            .collect()
+    }
+
+    pub fn count(&self, books: &[RawBook], required: &[String]) -> usize {
+        if required.is_empty() {
+            return books.len();
+        }
+        self.candidates(required)
+            .iter()
+            .filter(|&&idx| book_matches(&books[idx], required))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, paths: &[&str]) -> RawBook {
+        RawBook {
+            id: id.to_string(),
+            tag_paths: paths.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn ids(required: &[&str]) -> Vec<String> {
+        required.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn filters_and_counts_like_a_full_scan() {
+        let books = vec![
+            book("a", &["r/xx/yw/tb/g1"]),
+            book("b", &["r/xx/sx/rj/g1", "r/cz/yw/tb/g7"]),
+            book("c", &["r/xx/yw/tb/g2"]),
+        ];
+        let index = TagIndex::build(&books);
+
+        let found: Vec<&str> = index
+            .filter(&books, &ids(&["xx", "yw"]))
+            .iter()
+            .map(|b| b.id.as_str())
+            .collect();
+        assert_eq!(found, vec!["a", "c"]);
+        // b 的两条路径各含一半条件，不算命中
+        assert_eq!(index.count(&books, &ids(&["xx", "yw", "tb", "g7"])), 0);
+        assert_eq!(index.count(&books, &ids(&["cz", "yw"])), 1);
+        assert_eq!(index.count(&books, &ids(&["yw", "tb"])), 3);
+        assert_eq!(index.count(&books, &ids(&["unknown"])), 0);
+        assert_eq!(index.count(&books, &[]), 3);
+    }
+}
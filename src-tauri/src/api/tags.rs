@@ -1,24 +1,147 @@
-use crate::http;
-use crate::models::{DropdownOption, TagApiResponse, TagChild, TagHierarchy};
+use crate::http::{self, Conditional, Validators};
+use crate::models::{TagApiResponse, TagChild, TagHierarchy};
+use crate::storage;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 const TAG_URL: &str = "https://s-file-1.ykt.cbern.com.cn/zxx/ndrs/tags/tch_material_tag.json";
+// 持久化的标签树，断网时退回使用；同时保存校验值，重启后第一次请求也能走 304
+const TAG_TREE_CACHE_FILE: &str = "tag_tree.json";
+// 内存里的标签树超过这个时间后向服务端确认一次（条件请求，未变化时只有一个 304）
+const REVALIDATE_AFTER: Duration = Duration::from_secs(30 * 60);
+// 确认失败（断网）时先用旧树，隔一会儿再试，免得每次调用都等连接超时
+const RETRY_AFTER: Duration = Duration::from_secs(60);
 
-pub const SPECIAL_EDUCATION: &str = "特殊教育";
-pub const HIGH_SCHOOL: &str = "高中";
+struct CachedTree {
+    // 顶层分类列表，保持接口原始顺序（前端菜单/下拉框按此顺序展示）
+    tree: Arc<Vec<TagChild>>,
+    validators: Validators,
+    // 到此时刻前不再确认
+    fresh_until: Instant,
+    // 确认时书目的 module_version
+    module_version: Option<u64>,
+    // 确认失败、沿用的是旧树
+    offline: bool,
+}
+
+/// 标签树及其来源：offline 表示向服务端确认失败，用的是内存或磁盘上的旧树
+pub struct TagTree {
+    pub tree: Arc<Vec<TagChild>>,
+    pub offline: bool,
+}
+
+#[derive(Default)]
+struct TagTreeState {
+    cached: Option<CachedTree>,
+    // 最近一次拉到的书目 module_version，由 sync_version 更新
+    catalog_version: Option<u64>,
+}
+
+static TAG_TREE_CACHE: Lazy<Mutex<TagTreeState>> = Lazy::new(Default::default);
+// 合并并发的确认请求
+static REFRESH_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(Default::default);
+
+// 书目换了版本（可能新增了分类）或超过确认间隔时需要向服务端确认
+fn needs_revalidation(
+    now: Instant,
+    fresh_until: Instant,
+    tree_version: Option<u64>,
+    catalog_version: Option<u64>,
+) -> bool {
+    now >= fresh_until || catalog_version.is_some_and(|v| tree_version != Some(v))
+}
+
+fn cached_if_fresh() -> Option<TagTree> {
+    let state = TAG_TREE_CACHE.lock().unwrap();
+    let cached = state.cached.as_ref()?;
+    (!needs_revalidation(
+        Instant::now(),
+        cached.fresh_until,
+        cached.module_version,
+        state.catalog_version,
+    ))
+    .then(|| TagTree {
+        tree: Arc::clone(&cached.tree),
+        offline: cached.offline,
+    })
+}
 
-// 顶层分类列表，保持接口原始顺序（前端菜单/下拉框按此顺序展示）
-static TAG_TREE_CACHE: Lazy<Mutex<Option<Arc<Vec<TagChild>>>>> = Lazy::new(|| Mutex::new(None));
+/// 书目拉到 data_version 后调用：版本与标签树确认时不同，下次取标签树时重新确认
+pub fn sync_version(module_version: u64) {
+    TAG_TREE_CACHE.lock().unwrap().catalog_version = Some(module_version);
+}
 
-pub async fn fetch_tag_tree() -> Result<Arc<Vec<TagChild>>, String> {
-    if let Some(cached) = TAG_TREE_CACHE.lock().unwrap().clone() {
-        return Ok(cached);
+pub async fn fetch_tag_tree() -> Result<TagTree, String> {
+    if let Some(tree) = cached_if_fresh() {
+        return Ok(tree);
+    }
+    let _refresh = REFRESH_LOCK.lock().await;
+    // 等锁期间别的请求可能已经确认过
+    if let Some(tree) = cached_if_fresh() {
+        return Ok(tree);
     }
 
-    log::info!("拉取教材标签数据: {TAG_URL}");
-    let response: TagApiResponse = http::get_json(TAG_URL).await?;
-    let categories = response
+    let (previous, catalog_version) = {
+        let state = TAG_TREE_CACHE.lock().unwrap();
+        let previous = state
+            .cached
+            .as_ref()
+            .map(|c| (Arc::clone(&c.tree), c.validators.clone()));
+        (previous, state.catalog_version)
+    };
+    // 内存里没有时用磁盘上的树与校验值
+    let previous = match previous {
+        Some(previous) => Some(previous),
+        None => storage::read_json::<StoredTagTree>(TAG_TREE_CACHE_FILE)
+            .await
+            .map(|stored| {
+                log::info!("读取本地标签缓存 (版本 {:?})", stored.module_version);
+                (Arc::new(stored.tree), stored.validators)
+            }),
+    };
+    let validators = previous
+        .as_ref()
+        .map(|(_, validators)| validators.clone())
+        .unwrap_or_default();
+
+    log::info!("确认教材标签数据: {TAG_URL}");
+    let result = http::get_json_conditional::<TagApiResponse>(TAG_URL, &validators).await;
+    let (tree, validators, fresh_for, offline) = match (result, previous) {
+        (Ok(Conditional::NotModified), Some((tree, validators))) => {
+            log::info!("标签数据未变化");
+            (tree, validators, REVALIDATE_AFTER, false)
+        }
+        // 本地没有树却收到 304（不应出现），按失败处理
+        (Ok(Conditional::NotModified), None) => {
+            return Err("标签数据返回 304，但本地没有缓存".to_string());
+        }
+        (Ok(Conditional::Modified(response, validators)), _) => {
+            let tree = Arc::new(flatten_response(response));
+            persist(&tree, &validators, catalog_version).await;
+            (tree, validators, REVALIDATE_AFTER, false)
+        }
+        (Err(e), Some((tree, validators))) => {
+            log::warn!("标签数据获取失败，使用缓存: {e}");
+            (tree, validators, RETRY_AFTER, true)
+        }
+        (Err(e), None) => return Err(e),
+    };
+
+    TAG_TREE_CACHE.lock().unwrap().cached = Some(CachedTree {
+        tree: Arc::clone(&tree),
+        validators,
+        fresh_until: Instant::now() + fresh_for,
+        module_version: catalog_version,
+        offline,
+    });
+    Ok(TagTree { tree, offline })
+}
+
+fn flatten_response(response: TagApiResponse) -> Vec<TagChild> {
+    response
         .hierarchies
         .into_iter()
         .next()
@@ -26,15 +149,40 @@ pub async fn fetch_tag_tree() -> Result<Arc<Vec<TagChild>>, String> {
         .and_then(|children| children.into_iter().next())
         .and_then(|child| child.hierarchies)
         .map(flatten_children)
-        .unwrap_or_default();
+        .unwrap_or_default()
+}
 
-    let tree = Arc::new(categories);
-    *TAG_TREE_CACHE.lock().unwrap() = Some(Arc::clone(&tree));
-    Ok(tree)
+async fn persist(tree: &[TagChild], validators: &Validators, module_version: Option<u64>) {
+    let stored = StoredTagTreeRef {
+        module_version,
+        validators,
+        tree,
+    };
+    if let Err(e) = storage::write_json(TAG_TREE_CACHE_FILE, &stored).await {
+        log::warn!("保存标签缓存失败: {e}");
+    }
 }
 
-pub fn clear_cache() {
-    *TAG_TREE_CACHE.lock().unwrap() = None;
+// 标签树落盘时一并记下当时书目的 module_version，便于判断两者是否同批
+#[derive(Deserialize)]
+struct StoredTagTree {
+    #[serde(default)]
+    module_version: Option<u64>,
+    #[serde(default)]
+    validators: Validators,
+    tree: Vec<TagChild>,
+}
+
+#[derive(Serialize)]
+struct StoredTagTreeRef<'a> {
+    module_version: Option<u64>,
+    validators: &'a Validators,
+    tree: &'a [TagChild],
+}
+
+pub async fn clear_cache() {
+    TAG_TREE_CACHE.lock().unwrap().cached = None;
+    storage::remove(TAG_TREE_CACHE_FILE).await;
 }
 
 fn flatten_children(hierarchies: Vec<TagHierarchy>) -> Vec<TagChild> {
@@ -58,21 +206,89 @@ pub fn find_category<'a>(tree: &'a [TagChild], id: &str) -> Option<&'a TagChild>
     tree.iter().find(|c| c.tag_id == id)
 }
 
-// 按 分类/学科/版本/年级 的 tag_id 逐层向下查找
-pub fn find_path<'a>(tree: &'a [TagChild], ids: &[&str]) -> Option<&'a TagChild> {
+/// 沿 tag_id 路径逐层向下，返回路径上的各个节点；层数不限
+pub fn nodes_on_path<'a>(tree: &'a [TagChild], ids: &[&str]) -> Option<Vec<&'a TagChild>> {
     let (first, rest) = ids.split_first()?;
-    let mut node = find_category(tree, first)?;
+    let mut nodes = vec![find_category(tree, first)?];
     for id in rest {
-        node = children_of(node).find(|c| c.tag_id == *id)?;
+        let node = children_of(nodes.last()?).find(|c| c.tag_id == *id)?;
+        nodes.push(node);
     }
-    Some(node)
+    Some(nodes)
 }
 
-pub fn child_options(node: &TagChild) -> Vec<DropdownOption> {
-    children_of(node)
-        .map(|c| DropdownOption {
-            value: c.tag_id.clone(),
-            label: c.tag_name.clone(),
-        })
+pub fn find_path<'a>(tree: &'a [TagChild], ids: &[&str]) -> Option<&'a TagChild> {
+    nodes_on_path(tree, ids)?.pop()
+}
+
+/// tag_id → 名称，覆盖整棵标签树（书目的 tag_paths 只记 id，据此还原分类名）
+pub fn label_map(tree: &[TagChild]) -> HashMap<String, String> {
+    fn walk(node: &TagChild, map: &mut HashMap<String, String>) {
+        map.insert(node.tag_id.clone(), node.tag_name.clone());
+        for child in children_of(node) {
+            walk(child, map);
+        }
+    }
+    let mut map = HashMap::new();
+    for category in tree {
+        walk(category, &mut map);
+    }
+    map
+}
+
+/// 把 "id1/id2/.../idN" 形式的 tag_path 还原成名称链；树里没有的 id（如根节点）跳过
+pub fn resolve_path(names: &HashMap<String, String>, tag_path: &str) -> Vec<String> {
+    tag_path
+        .split('/')
+        .filter_map(|id| names.get(id).cloned())
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn walks_paths_of_any_depth() {
+        // 特殊教育比普通学段多一层「年份」
+        let tree: Vec<TagChild> = serde_json::from_value(json!([{
+            "tag_id": "tj", "tag_name": "特殊教育",
+            "hierarchies": [{ "children": [{
+                "tag_id": "yw", "tag_name": "语文",
+                "hierarchies": [{ "children": [{
+                    "tag_id": "rj", "tag_name": "人教版",
+                    "hierarchies": [{ "children": [{
+                        "tag_id": "g1", "tag_name": "一年级",
+                        "hierarchies": [{ "children": [
+                            { "tag_id": "y2016", "tag_name": "2016年", "hierarchies": null }
+                        ] }]
+                    }] }]
+                }] }]
+            }] }]
+        }]))
+        .unwrap();
+
+        let nodes = nodes_on_path(&tree, &["tj", "yw", "rj", "g1", "y2016"]).unwrap();
+        let names: Vec<&str> = nodes.iter().map(|n| n.tag_name.as_str()).collect();
+        assert_eq!(names, vec!["特殊教育", "语文", "人教版", "一年级", "2016年"]);
+        assert!(children_of(nodes[4]).next().is_none());
+        assert_eq!(children_of(nodes[3]).count(), 1);
+        assert!(nodes_on_path(&tree, &["tj", "sx"]).is_none());
+        assert_eq!(find_path(&tree, &["tj", "yw"]).unwrap().tag_name, "语文");
+    }
+
+    #[test]
+    fn revalidates_after_ttl_or_catalog_change() {
+        let now = Instant::now();
+        let later = now + REVALIDATE_AFTER;
+        assert!(!needs_revalidation(now, later, Some(7), Some(7)));
+        // 书目还没加载过时只看确认间隔
+        assert!(!needs_revalidation(now, later, Some(7), None));
+        assert!(!needs_revalidation(now, later, None, None));
+        // 书目出了新版本，标签树可能多了分类
+        assert!(needs_revalidation(now, later, Some(7), Some(8)));
+        assert!(needs_revalidation(now, later, None, Some(8)));
+        assert!(needs_revalidation(later, later, Some(7), Some(7)));
+    }
+}
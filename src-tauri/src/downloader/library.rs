@@ -0,0 +1,117 @@
+// 本地教材库：每本下载完成的教材记下所用的源文件地址与转码时间戳（library.json），
+// 之后与平台当前的详情比对，找出出版社已重新发布、需要重新下载的教材。
+// 记录里保存原始下载请求，「只重下有更新的」时前端直接按它重新入队。
+
+use super::task;
+use crate::api::books::{self, SourceVersion};
+use crate::api::images;
+use crate::models::TextbookDownloadInfo;
+use crate::storage;
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::sync::Mutex;
+
+const LIBRARY_FILE: &str = "library.json";
+// 比对时并发查询详情的数量
+const CHECK_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub resource_id: String,
+    pub title: String,
+    pub file_path: String,
+    // 实际下载用的地址（可能是 pdf.pdf 别名，也可能是详情里的源文件地址）
+    pub downloaded_from: String,
+    // 下载时详情里的源文件版本
+    pub source: SourceVersion,
+    // 下载完成时间（unix 秒）
+    pub downloaded_at: u64,
+    pub info: TextbookDownloadInfo,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutdatedTextbook {
+    pub resource_id: String,
+    pub title: String,
+    pub file_path: String,
+    pub downloaded: SourceVersion,
+    pub current: SourceVersion,
+    // 原始下载请求，重新下载时原样使用
+    pub info: TextbookDownloadInfo,
+}
+
+// 串行化 library.json 的读改写
+static LIBRARY_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+async fn load() -> Vec<LibraryEntry> {
+    storage::read_json(LIBRARY_FILE).await.unwrap_or_default()
+}
+
+/// 记录一本刚下载完成的教材；同一资源只保留最近一次
+pub(super) async fn record(info: &TextbookDownloadInfo, downloaded_from: &str, file_path: &Path) {
+    let Some(resource_id) = task::resource_id_of(info).map(str::to_string) else {
+        return;
+    };
+    let source = books::source_version(&resource_id).await.unwrap_or_default();
+    let entry = LibraryEntry {
+        title: info.title.clone(),
+        file_path: file_path.to_string_lossy().into_owned(),
+        downloaded_from: downloaded_from.to_string(),
+        source,
+        downloaded_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        info: info.clone(),
+        resource_id,
+    };
+
+    let _guard = LIBRARY_LOCK.lock().await;
+    let mut entries = load().await;
+    entries.retain(|e| e.resource_id != entry.resource_id);
+    entries.push(entry);
+    if let Err(e) = storage::write_json(LIBRARY_FILE, &entries).await {
+        log::warn!("保存教材库记录失败: {e}");
+    }
+}
+
+/// 逐本比对本地教材与平台当前版本，返回有更新的。本地文件已删除的、
+/// 下载时没能取到详情（无从比较）的不检查
+pub(super) async fn find_outdated() -> Result<Vec<OutdatedTextbook>, String> {
+    // 先取一次书目：data_version 变化时详情缓存随之作废，下面拿到的才是最新详情
+    books::get_raw_books().await?;
+
+    let entries = {
+        let _guard = LIBRARY_LOCK.lock().await;
+        load().await
+    };
+    let outdated = futures_util::stream::iter(entries)
+        .filter(|entry| {
+            let comparable =
+                entry.source != SourceVersion::default() && Path::new(&entry.file_path).exists();
+            async move { comparable }
+        })
+        .map(|entry| async move {
+            let current = books::source_version(&entry.resource_id).await?;
+            if !current.is_newer_than(&entry.source) {
+                return None;
+            }
+            images::forget_cover(&entry.resource_id).await;
+            Some(OutdatedTextbook {
+                resource_id: entry.resource_id,
+                title: entry.title,
+                file_path: entry.file_path,
+                downloaded: entry.source,
+                current,
+                info: entry.info,
+            })
+        })
+        .buffer_unordered(CHECK_CONCURRENCY)
+        .filter_map(|item| async move { item })
+        .collect::<Vec<_>>()
+        .await;
+    log::info!("教材更新检查完成，{} 本有新版本", outdated.len());
+    Ok(outdated)
+}
@@ -7,10 +7,14 @@
 //   4. 真正的 16 字节密钥 = AES-ECB-decrypt(密钥=sign, 密文=base64 解码后的 key)
 // 切片再用 AES-128-CBC(key, iv) + PKCS7 解密。全流程已离线验证。
 //
-// m3u8 与 ts 均需携带占位 MAC 鉴权头（见 task::create_request）。
+// m3u8 与 ts 均需携带占位 MAC 鉴权头（见 task::with_auth，只发给平台域名）。
 //
-// 断点续传：解密后的切片逐个落盘到 <最终名>.parts/ 目录，中断后重试会跳过
-// 已存在的切片（密钥每次重新握手获取，不落盘）；全部就绪后按序流式拼接。
+// 解密校验：解出的明文必须是 MPEG-TS（每 188 字节一个包，包首字节 0x47）。
+// 不符时重新握手一次换密钥；新密钥仍解不出 TS 即判定密钥/IV 有误，立即终止整个任务，
+// 而不是下完几百个切片才拼出一个打不开的文件。
+//
+// 断点续传：解密后的切片逐个落盘到 <最终名>.parts/ 目录（清单与校验见 parts.rs），
+// 中断后重试会跳过校验通过的切片（密钥每次重新握手获取，不落盘）；全部就绪后按序流式拼接。
 
 use crate::http::CLIENT;
 use aes::Aes128;
@@ -18,7 +22,7 @@ use aes::cipher::{BlockDecryptMut, KeyIvInit, KeyInit, block_padding::Pkcs7};
 use futures_util::stream::{self, StreamExt};
 use md5::{Digest, Md5};
 use serde::Deserialize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
@@ -26,31 +30,154 @@ use tokio::fs;
 use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::process::Command;
 use tokio_util::sync::CancellationToken;
+use url::Url;
 
-use super::task::{DownloadEventEmitter, USER_AGENT, path_with_suffix};
+use super::parts::PartsCache;
+use super::task::{self, DownloadEventEmitter, USER_AGENT, path_with_suffix};
+use super::throttle::{self, AdaptiveLimiter};
 
 type Aes128CbcDec = cbc::Decryptor<Aes128>;
 type Aes128EcbDec = ecb::Decryptor<Aes128>;
 
-// 切片并发下载数：偏大以吃满带宽，但受全局并发（前端下载池调度）约束
-const SEGMENT_CONCURRENCY: usize = 8;
 // 单个切片的总尝试次数：瞬时网络抖动不该让几百个切片的任务整体失败
 const SEGMENT_ATTEMPTS: usize = 3;
 
+// MPEG-TS 包长与同步字节
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+const DECRYPTION_ERROR: &str = "解密校验失败";
+
 // 鉴权失败重试无意义（令牌过期只会一直 401/403），直接失败让上层提示换令牌
 fn is_auth_error(message: &str) -> bool {
     message.contains("HTTP 401") || message.contains("HTTP 403")
 }
 
+// 密钥/IV 错误：重试同样解不出来，整个任务立即失败
+fn is_decryption_error(message: &str) -> bool {
+    message.contains(DECRYPTION_ERROR)
+}
+
+/// 转封装时写入 MP4 的元数据。文件被改名、移出共享目录后，
+/// Plex / Jellyfin 等媒体库仍能据此识别出是哪门课的哪个资源。
+#[derive(Debug, Default)]
+pub(super) struct VideoMetadata {
+    pub title: String,
+    pub course_title: Option<String>,
+    pub category_path: Vec<String>,
+    pub source_id: String,
+    // 平台课程页地址
+    pub source_url: String,
+    pub cover_url: String,
+}
+
+impl VideoMetadata {
+    // 映射到 ffmpeg mp4 muxer 认识的标签：课程名同时写 album 与 show，
+    // 分类路径写 genre，资源 id 写 episode_id，平台地址写 comment
+    fn ffmpeg_args(&self) -> Vec<String> {
+        let course = self.course_title.clone().unwrap_or_default();
+        let category = self
+            .category_path
+            .iter()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" / ");
+        [
+            ("title", self.title.clone()),
+            ("album", course.clone()),
+            ("show", course),
+            ("genre", category),
+            ("episode_id", self.source_id.clone()),
+            ("comment", self.source_url.clone()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .flat_map(|(key, value)| ["-metadata".to_string(), format!("{key}={value}")])
+        .collect()
+    }
+}
+
+/// 时间段下载（秒）：只下载与 [start, end) 有重叠的切片。end 为空表示到结尾；
+/// precise 时再用 ffmpeg 重新编码，精确裁到起止时间（切片级选取只能对齐到切片边界）。
+#[derive(Debug, Clone, Copy)]
+pub(super) struct TimeRange {
+    pub start: f64,
+    pub end: Option<f64>,
+    pub precise: bool,
+}
+
+impl TimeRange {
+    // 用于切片清单与文件名，如 "05m00s-10m30s"
+    pub(super) fn label(&self) -> String {
+        let end = self.end.map_or_else(|| "end".to_string(), format_clock);
+        format!("{}-{end}", format_clock(self.start))
+    }
+}
+
+fn format_clock(seconds: f64) -> String {
+    let total = seconds.max(0.0).round() as u64;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{h}h{m:02}m{s:02}s")
+    } else {
+        format!("{m:02}m{s:02}s")
+    }
+}
+
+// 精确裁剪参数：offset 为时间段起点相对拼接后首个切片的偏移
+struct Trim {
+    offset: f64,
+    duration: Option<f64>,
+}
+
+// 按 #EXTINF 时长挑出与时间段有重叠的切片，返回切片序号与裁剪参数
+fn select_segments(durations: &[f64], range: &TimeRange) -> Result<(Vec<usize>, Trim), String> {
+    if durations.iter().all(|d| *d <= 0.0) {
+        return Err("播放列表缺少切片时长（#EXTINF），无法按时间段下载".to_string());
+    }
+    let mut selected = Vec::new();
+    let mut first_start = None;
+    let mut seg_start = 0.0;
+    for (idx, duration) in durations.iter().enumerate() {
+        let seg_end = seg_start + duration;
+        let before_end = range.end.is_none_or(|end| seg_start < end);
+        if seg_end > range.start && before_end {
+            first_start.get_or_insert(seg_start);
+            selected.push(idx);
+        }
+        seg_start = seg_end;
+    }
+    let Some(first_start) = first_start else {
+        return Err(format!("时间段超出视频长度（共 {}）", format_clock(seg_start)));
+    };
+    Ok((
+        selected,
+        Trim {
+            offset: range.start - first_start,
+            duration: range.end.map(|end| end - range.start),
+        },
+    ))
+}
+
+/// 视频成品的生成方式：ffmpeg 路径（转封装/裁剪用）、写入的元数据、可选的时间段
+#[derive(Debug, Default)]
+pub(super) struct VideoOutput {
+    pub ffmpeg_path: Option<String>,
+    pub metadata: VideoMetadata,
+    pub range: Option<TimeRange>,
+}
+
 struct KeyInfo {
     key: [u8; 16],
     iv: [u8; 16],
 }
 
-struct Playlist {
-    segments: Vec<String>,
-    key_url: Option<String>,
-    iv_hex: Option<String>,
+pub(super) struct Playlist {
+    pub segments: Vec<String>,
+    // 各切片 #EXTINF 时长（秒），与 segments 一一对应；缺失记 0
+    pub durations: Vec<f64>,
+    pub key_url: Option<String>,
+    pub iv_hex: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -63,13 +190,12 @@ struct KeyResp {
     key: String,
 }
 
-// 带占位鉴权拉取 URL 文本
-async fn get_text_authed(url: &str, token: Option<&str>) -> Result<String, String> {
-    let mut req = CLIENT.get(url).header(reqwest::header::USER_AGENT, USER_AGENT);
-    if let Some(t) = token {
-        req = req.header("x-nd-auth", format!("MAC id=\"{t}\",nonce=\"0\",mac=\"0\""));
-    }
-    let resp = req.send().await.map_err(|e| format!("请求失败: {e}"))?;
+// 带占位鉴权拉取 URL 文本（非平台域名不带令牌）
+pub(super) async fn get_text_authed(url: &str, token: Option<&str>) -> Result<String, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("无效的地址 {url}: {e}"))?;
+    let req = CLIENT.get(parsed.clone()).header(reqwest::header::USER_AGENT, USER_AGENT);
+    let resp = task::with_auth(req, &parsed, token)
+        .send().await.map_err(|e| format!("请求失败: {e}"))?;
     if !resp.status().is_success() {
         return Err(format!("HTTP {}", resp.status()));
     }
@@ -77,11 +203,10 @@ async fn get_text_authed(url: &str, token: Option<&str>) -> Result<String, Strin
 }
 
 async fn get_bytes_authed(url: &str, token: Option<&str>) -> Result<Vec<u8>, String> {
-    let mut req = CLIENT.get(url).header(reqwest::header::USER_AGENT, USER_AGENT);
-    if let Some(t) = token {
-        req = req.header("x-nd-auth", format!("MAC id=\"{t}\",nonce=\"0\",mac=\"0\""));
-    }
-    let resp = req.send().await.map_err(|e| format!("请求失败: {e}"))?;
+    let parsed = Url::parse(url).map_err(|e| format!("无效的地址 {url}: {e}"))?;
+    let req = CLIENT.get(parsed.clone()).header(reqwest::header::USER_AGENT, USER_AGENT);
+    let resp = task::with_auth(req, &parsed, token)
+        .send().await.map_err(|e| format!("请求失败: {e}"))?;
     if !resp.status().is_success() {
         return Err(format!("HTTP {}", resp.status()));
     }
@@ -91,16 +216,24 @@ async fn get_bytes_authed(url: &str, token: Option<&str>) -> Result<Vec<u8>, Str
         .map_err(|e| format!("读取响应失败: {e}"))
 }
 
-// 解析 m3u8，取切片列表、KEY 地址与 IV。相对地址按 base_url 拼成绝对地址。
-fn parse_playlist(content: &str, base_url: &str) -> Playlist {
+// 解析 m3u8，取切片列表、时长、KEY 地址与 IV。相对地址按 base_url 拼成绝对地址。
+pub(super) fn parse_playlist(content: &str, base_url: &str) -> Playlist {
     let base = &base_url[..=base_url.rfind('/').unwrap_or(0)];
     let mut segments = Vec::new();
+    let mut durations = Vec::new();
+    let mut pending_duration = 0.0;
     let mut key_url = None;
     let mut iv_hex = None;
 
     for line in content.lines() {
         let line = line.trim();
-        if line.starts_with("#EXT-X-KEY") {
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            pending_duration = rest
+                .split(',')
+                .next()
+                .and_then(|d| d.trim().parse().ok())
+                .unwrap_or(0.0);
+        } else if line.starts_with("#EXT-X-KEY") {
             if let Some(rest) = line.split("URI=\"").nth(1) {
                 if let Some(u) = rest.split('"').next() {
                     key_url = Some(u.to_string());
@@ -120,11 +253,13 @@ fn parse_playlist(content: &str, base_url: &str) -> Playlist {
             } else {
                 segments.push(format!("{base}{line}"));
             }
+            durations.push(std::mem::take(&mut pending_duration));
         }
     }
 
     Playlist {
         segments,
+        durations,
         key_url,
         iv_hex,
     }
@@ -198,89 +333,313 @@ fn decrypt_segment(data: &[u8], key: &KeyInfo) -> Result<Vec<u8>, String> {
     Ok(dec.to_vec())
 }
 
-fn segment_file_name(idx: usize) -> String {
-    format!("seg_{idx:05}.ts")
+// 密钥 / IV 错误时 PKCS7 去填充仍有约 1/256 的概率「成功」，解出的垃圾靠同步字节识别
+fn has_ts_sync(data: &[u8]) -> bool {
+    data.len() >= TS_PACKET_SIZE
+        && data
+            .chunks_exact(TS_PACKET_SIZE)
+            .all(|packet| packet[0] == TS_SYNC_BYTE)
+}
+
+struct KeyState {
+    current: Arc<KeyInfo>,
+    // 已重新握手过（整个任务只有一次机会）
+    refreshed: bool,
+    // 重新握手拿到的仍是同一把密钥：密钥可信，再出现校验失败只能是切片本身损坏
+    confirmed: bool,
+}
+
+// 所有切片共用的解密密钥。校验失败时由第一个发现的切片负责重新握手，其余切片复用结果
+pub(super) struct SegmentKey {
+    key_url: String,
+    iv_hex: Option<String>,
+    token: Option<String>,
+    state: tokio::sync::Mutex<KeyState>,
+}
+
+impl SegmentKey {
+    pub(super) async fn handshake(
+        key_url: &str,
+        iv_hex: Option<&str>,
+        token: Option<&str>,
+    ) -> Result<Self, String> {
+        let key = fetch_key(key_url, iv_hex, token).await?;
+        Ok(Self {
+            key_url: key_url.to_string(),
+            iv_hex: iv_hex.map(str::to_string),
+            token: token.map(str::to_string),
+            state: tokio::sync::Mutex::new(KeyState {
+                current: Arc::new(key),
+                refreshed: false,
+                confirmed: false,
+            }),
+        })
+    }
+
+    // 解密并校验 TS 同步字节。返回的错误里，密钥错误带 DECRYPTION_ERROR 标记（不可重试），
+    // 切片损坏则是普通错误（重新下载该切片）
+    async fn decrypt(&self, raw: &[u8]) -> Result<Vec<u8>, String> {
+        let used = Arc::clone(&self.state.lock().await.current);
+        // 密钥/IV 不对时 CBC 多半先在去填充时失败，与同步字节不符一样走重新握手
+        if let Some(plain) = decrypt_segment(raw, &used).ok().filter(|p| has_ts_sync(p)) {
+            return Ok(plain);
+        }
+
+        let retry_key = {
+            let mut state = self.state.lock().await;
+            if !Arc::ptr_eq(&state.current, &used) {
+                // 别的切片已经换过密钥
+                Arc::clone(&state.current)
+            } else if state.confirmed {
+                return Err(corrupt_segment_error());
+            } else if state.refreshed {
+                return Err(wrong_key_error());
+            } else {
+                log::warn!("切片解密失败或 TS 同步字节不符，重新握手获取密钥");
+                // 握手本身失败（网络抖动）不算用掉机会，下一个校验失败的切片还会再试
+                let fresh = fetch_key(&self.key_url, self.iv_hex.as_deref(), self.token.as_deref())
+                    .await?;
+                state.refreshed = true;
+                if fresh.key == used.key && fresh.iv == used.iv {
+                    state.confirmed = true;
+                    return Err(corrupt_segment_error());
+                }
+                // 持锁校验新密钥：解出有效 TS 即确认可信，之后的校验失败按切片损坏重试
+                let result = decrypt_segment(raw, &fresh).ok().filter(|p| has_ts_sync(p));
+                state.current = Arc::new(fresh);
+                return match result {
+                    Some(plain) => {
+                        state.confirmed = true;
+                        Ok(plain)
+                    }
+                    None => Err(wrong_key_error()),
+                };
+            }
+        };
+
+        match decrypt_segment(raw, &retry_key) {
+            Ok(plain) if has_ts_sync(&plain) => {
+                self.state.lock().await.confirmed = true;
+                Ok(plain)
+            }
+            _ if self.state.lock().await.confirmed => Err(corrupt_segment_error()),
+            _ => Err(wrong_key_error()),
+        }
+    }
+}
+
+fn corrupt_segment_error() -> String {
+    "切片数据损坏（解密或 TS 同步字节校验失败）".to_string()
 }
 
-// 下载并解密单个切片，带重试（指数退避）。解密失败通常是响应被截断，同样值得重试；
-// 鉴权错误与取消立即返回。
-async fn fetch_segment_with_retry(
+fn wrong_key_error() -> String {
+    format!("{DECRYPTION_ERROR}：解密结果不是有效的 MPEG-TS，视频密钥或 IV 不正确")
+}
+
+// 连续失败这么多次的镜像暂不分配，直到它再成功一次（其他镜像都不可用时仍会用它）
+const MIRROR_FAILURE_LIMIT: usize = 3;
+
+// 地址所在目录（含结尾的 /），忽略查询串
+fn dir_of(url: &str) -> &str {
+    let path_end = url.find(['?', '#']).unwrap_or(url.len());
+    &url[..=url[..path_end].rfind('/').unwrap_or(0)]
+}
+
+/// 同一视频在多个 CDN 镜像（ti_storages 的 r1/r2/r3）上的播放列表。
+/// 切片地址按主播放列表所在目录换算到各镜像；切片按序号轮流分配起始镜像，
+/// 失败重试时换下一个镜像
+pub(super) struct Mirrors {
+    // 各镜像播放列表所在目录，第 0 个为实际拉取的主播放列表
+    bases: Vec<String>,
+    // 各镜像的连续失败次数
+    failures: Vec<AtomicUsize>,
+}
+
+impl Mirrors {
+    pub(super) fn new(m3u8_url: &str, mirror_urls: &[String]) -> Self {
+        let mut bases = vec![dir_of(m3u8_url).to_string()];
+        for url in mirror_urls {
+            let base = dir_of(url);
+            if base.starts_with("http") && !bases.iter().any(|b| b == base) {
+                bases.push(base.to_string());
+            }
+        }
+        let failures = bases.iter().map(|_| AtomicUsize::new(0)).collect();
+        Self { bases, failures }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.bases.len()
+    }
+
+    // 第 attempt 次（从 0 计）尝试用哪个镜像、请求哪个地址。
+    // 不在主播放列表目录下的切片（绝对地址指向别处）无法换算，只用原地址
+    fn pick(&self, seg_url: &str, idx: usize, attempt: usize) -> (usize, String) {
+        let Some(rest) = seg_url.strip_prefix(self.bases[0].as_str()) else {
+            return (0, seg_url.to_string());
+        };
+        let n = self.bases.len();
+        let rotation: Vec<usize> = (0..n).map(|k| (idx + k) % n).collect();
+        let healthy: Vec<usize> = rotation
+            .iter()
+            .copied()
+            .filter(|&m| self.failures[m].load(Ordering::Relaxed) < MIRROR_FAILURE_LIMIT)
+            .collect();
+        let order = if healthy.is_empty() { &rotation } else { &healthy };
+        let mirror = order[attempt % order.len()];
+        (mirror, format!("{}{rest}", self.bases[mirror]))
+    }
+
+    // 切片可用的镜像数：不在主播放列表目录下的切片只有原地址一个
+    fn candidates(&self, seg_url: &str) -> usize {
+        if seg_url.starts_with(self.bases[0].as_str()) {
+            self.bases.len()
+        } else {
+            1
+        }
+    }
+
+    // 同 pick，但跳过 avoid 里的镜像（已对该切片返回鉴权错误的）。剩下的镜像都暂停分配时
+    // 仍按轮转顺序用它们；全被跳过时退回 pick
+    fn pick_avoiding(
+        &self,
+        seg_url: &str,
+        idx: usize,
+        attempt: usize,
+        avoid: &[usize],
+    ) -> (usize, String) {
+        let n = self.bases.len();
+        if let Some(found) = (attempt..attempt + n)
+            .map(|a| self.pick(seg_url, idx, a))
+            .find(|(mirror, _)| !avoid.contains(mirror))
+        {
+            return found;
+        }
+        match seg_url.strip_prefix(self.bases[0].as_str()) {
+            Some(rest) => (0..n)
+                .map(|k| (idx + attempt + k) % n)
+                .find(|m| !avoid.contains(m))
+                .map(|m| (m, format!("{}{rest}", self.bases[m])))
+                .unwrap_or_else(|| self.pick(seg_url, idx, attempt)),
+            None => self.pick(seg_url, idx, attempt),
+        }
+    }
+
+    fn record(&self, mirror: usize, ok: bool) {
+        if ok {
+            self.failures[mirror].store(0, Ordering::Relaxed);
+        } else if self.failures[mirror].fetch_add(1, Ordering::Relaxed) + 1 == MIRROR_FAILURE_LIMIT
+        {
+            log::warn!("镜像连续失败，暂停分配: {}", self.bases[mirror]);
+        }
+    }
+}
+
+// 下载并解密单个切片，带重试（指数退避）。有多个镜像时每次尝试换一个镜像，
+// 换镜像重试不必等退避（限流除外）。解密失败通常是响应被截断，同样值得重试。
+// 鉴权错误可能只是某个镜像配置不对或开了防盗链：记下该镜像、换下一个镜像，
+// 且不占重试次数；所有镜像都返回鉴权错误才放弃。密钥错误与取消立即返回。
+// 每次请求占用一路自适应并发和一个 host 连接名额，退避等待期间不占名额；
+// 请求结果反馈给限流器调整并发。
+pub(super) async fn fetch_segment_with_retry(
     seg_url: &str,
+    mirrors: &Mirrors,
     token: Option<&str>,
-    key_info: Option<&KeyInfo>,
+    key: Option<&SegmentKey>,
     idx: usize,
+    limiter: &AdaptiveLimiter,
     cancellation_token: &CancellationToken,
 ) -> Result<Vec<u8>, String> {
     let mut delay_ms = 500u64;
-    for attempt in 1..=SEGMENT_ATTEMPTS {
+    // 对本切片返回过鉴权错误的镜像
+    let mut auth_failed: Vec<usize> = Vec::new();
+    // 已用掉的重试次数（鉴权错误换镜像不计）与总请求次数（决定轮到哪个镜像）
+    let mut attempt = 0usize;
+    let mut requests = 0usize;
+    loop {
         if cancellation_token.is_cancelled() {
             return Err("下载已取消".to_string());
         }
 
-        let result = match get_bytes_authed(seg_url, token).await {
-            Ok(raw) => match key_info {
-                Some(k) => decrypt_segment(&raw, k),
-                None => Ok(raw),
-            },
-            Err(e) => Err(e),
+        let (mirror, url) = mirrors.pick_avoiding(seg_url, idx, requests, &auth_failed);
+        requests += 1;
+        let result = {
+            let _permit = limiter.acquire().await;
+            let _slot = throttle::acquire_host_slot(&url).await;
+            match get_bytes_authed(&url, token).await {
+                Ok(raw) => match key {
+                    Some(k) => k.decrypt(&raw).await,
+                    None => Ok(raw),
+                },
+                Err(e) => Err(e),
+            }
         };
 
-        match result {
-            Ok(bytes) => return Ok(bytes),
-            Err(e) if attempt < SEGMENT_ATTEMPTS && !is_auth_error(&e) => {
-                log::warn!("切片 {idx} 第 {attempt} 次尝试失败，将重试: {e}");
-                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
-                delay_ms *= 2;
+        match &result {
+            Ok(_) => {
+                limiter.on_success();
+                mirrors.record(mirror, true);
             }
-            Err(e) => return Err(format!("切片 {idx} 下载失败: {e}")),
+            Err(e) if is_auth_error(e) => mirrors.record(mirror, false),
+            Err(e) if !is_decryption_error(e) => {
+                limiter.on_error();
+                mirrors.record(mirror, false);
+            }
+            Err(_) => {}
         }
-    }
-    unreachable!("重试循环必然提前返回")
-}
-
-// 建立/校验切片缓存目录。playlist 变化（地址或切片数不同）时清空重建，避免错位拼接
-async fn prepare_parts_dir(parts_dir: &Path, m3u8_url: &str, total: usize) -> Result<(), String> {
-    let manifest_path = parts_dir.join("manifest.txt");
-    let manifest = format!("{m3u8_url}\n{total}");
 
-    if fs::try_exists(parts_dir).await.unwrap_or(false) {
-        let stale = match fs::read_to_string(&manifest_path).await {
-            Ok(prev) => prev != manifest,
-            Err(_) => true,
+        let e = match result {
+            Ok(bytes) => return Ok(bytes),
+            Err(e) if is_decryption_error(&e) => return Err(e),
+            Err(e) => e,
         };
-        if stale {
-            fs::remove_dir_all(parts_dir)
-                .await
-                .map_err(|e| format!("清理切片缓存失败: {e}"))?;
+
+        if is_auth_error(&e) {
+            if !auth_failed.contains(&mirror) {
+                auth_failed.push(mirror);
+            }
+            if auth_failed.len() >= mirrors.candidates(seg_url) {
+                return Err(format!("切片 {idx} 下载失败: {e}"));
+            }
+            log::warn!("切片 {idx} 在镜像 {mirror} 上鉴权失败，换镜像重试: {e}");
+            continue;
         }
-    }
 
-    fs::create_dir_all(parts_dir)
-        .await
-        .map_err(|e| format!("创建切片缓存目录失败: {e}"))?;
-    fs::write(&manifest_path, manifest)
-        .await
-        .map_err(|e| format!("写入切片清单失败: {e}"))?;
-    Ok(())
+        attempt += 1;
+        if attempt >= SEGMENT_ATTEMPTS {
+            return Err(format!("切片 {idx} 下载失败: {e}"));
+        }
+        log::warn!("切片 {idx} 第 {attempt} 次尝试失败，将重试: {e}");
+        let throttled = throttle::is_throttle_error(&e);
+        let (next_mirror, _) = mirrors.pick_avoiding(seg_url, idx, requests, &auth_failed);
+        if next_mirror != mirror && !throttled {
+            continue;
+        }
+        // 限流 / 过载时多等一轮，给 CDN 喘息
+        if throttled {
+            delay_ms *= 2;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+        delay_ms *= 2;
+    }
 }
 
-// 依序把切片拼接为单个 .ts（逐片读写，峰值内存只有单个切片大小）
+// 依序把选中的切片拼接为单个 .ts（逐片读写，峰值内存只有单个切片大小）
 async fn assemble_ts(
     ts_path: &Path,
-    parts_dir: &Path,
-    total: usize,
+    cache: &PartsCache,
+    indices: &[usize],
     cancellation_token: &CancellationToken,
 ) -> Result<(), String> {
     let file = fs::File::create(ts_path)
         .await
         .map_err(|e| format!("创建文件失败: {e}"))?;
     let mut writer = BufWriter::new(file);
-    for idx in 0..total {
+    for &idx in indices {
         if cancellation_token.is_cancelled() {
             return Err("下载已取消".to_string());
         }
-        let seg_path = parts_dir.join(segment_file_name(idx));
-        let bytes = fs::read(&seg_path)
+        let bytes = fs::read(cache.segment_path(idx))
             .await
             .map_err(|e| format!("切片 {idx} 缺失: {e}"))?;
         writer
@@ -292,17 +651,29 @@ async fn assemble_ts(
     Ok(())
 }
 
-/// 下载并解密整个 m3u8 视频。返回实际写入的文件路径，以及一条可选的告警
-/// （配置了 ffmpeg 却没能转封装成 .mp4 时，需要让用户知道原因，而不是默默存成 .ts）。
+/// 下载并解密 m3u8 视频（指定了时间段时只下相关切片）。返回实际写入的文件路径，
+/// 以及一条可选的告警（配置了 ffmpeg 却没能转封装成 .mp4 时，需要让用户知道原因，
+/// 而不是默默存成 .ts）。
+/// mirror_urls 是同一播放列表在其他 CDN 镜像上的地址，切片请求会分散到各镜像。
 /// 中断/取消会保留 <out_path>.parts/ 中已下载的切片，下次调用自动续传。
 pub(super) async fn download(
     m3u8_url: &str,
+    mirror_urls: &[String],
     token: Option<&str>,
     out_path: &Path,
-    ffmpeg_path: Option<&str>,
+    output: &VideoOutput,
     cancellation_token: &CancellationToken,
     emitter: &DownloadEventEmitter,
-) -> Result<(std::path::PathBuf, Option<String>), String> {
+) -> Result<(PathBuf, Option<String>), String> {
+    // 精确裁剪离不开 ffmpeg，没有时不要下完才退回切片边界，直接告诉用户
+    let has_ffmpeg = output.ffmpeg_path.as_deref().is_some_and(|p| !p.is_empty());
+    if output.range.as_ref().is_some_and(|range| range.precise) && !has_ffmpeg {
+        return Err(
+            "精确裁剪需要 ffmpeg：请在「设置」中指定 ffmpeg 路径，或取消「精确裁剪」按切片边界下载"
+                .to_string(),
+        );
+    }
+
     // 1. 拉 m3u8
     let content = get_text_authed(m3u8_url, token)
         .await
@@ -314,61 +685,78 @@ pub(super) async fn download(
     }
 
     // 2. 取密钥（如加密）。续传时也重新握手，密钥不落盘
-    let key_info = match playlist.key_url.as_deref() {
-        Some(ku) => Some(Arc::new(fetch_key(ku, playlist.iv_hex.as_deref(), token).await?)),
+    let key = match playlist.key_url.as_deref() {
+        Some(ku) => Some(Arc::new(
+            SegmentKey::handshake(ku, playlist.iv_hex.as_deref(), token).await?,
+        )),
         None => None,
     };
 
-    let total = playlist.segments.len();
+    // 时间段下载只取有重叠的切片；切片文件仍按播放列表里的序号命名
+    let (indices, trim) = match output.range.as_ref() {
+        Some(range) => {
+            let (indices, trim) = select_segments(&playlist.durations, range)?;
+            log::info!("时间段 {} 对应切片 {} 个", range.label(), indices.len());
+            (indices, range.precise.then_some(trim))
+        }
+        None => ((0..playlist.segments.len()).collect(), None),
+    };
+    let total = indices.len();
 
     // 3. 切片缓存目录：<最终名>.parts/
     let parts_dir = path_with_suffix(out_path, ".parts");
-    prepare_parts_dir(&parts_dir, m3u8_url, total).await?;
+    let cache = PartsCache::open(&parts_dir, m3u8_url, &playlist, output.range.as_ref()).await?;
+    let cache = cache.as_ref();
 
     emitter.emit_progress(0, 0, 0, None);
 
-    // 4. 并发下载 + 解密切片，逐片落盘；已存在的切片直接跳过（续传）
+    // 4. 并发下载 + 解密切片，逐片落盘；通过清单校验的已缓存切片直接跳过（续传）。
+    // 实际在途请求数由自适应限流器与 host 连接预算决定，buffer 只是上界
+    let limiter = AdaptiveLimiter::new();
+    let limiter = &limiter;
+    let mirrors = Mirrors::new(m3u8_url, mirror_urls);
+    let mirrors = &mirrors;
+    if mirrors.len() > 1 {
+        log::info!("切片分散到 {} 个镜像下载", mirrors.len());
+    }
     let done_count = Arc::new(AtomicUsize::new(0));
     let done_bytes = Arc::new(AtomicU64::new(0));
     // 续传时跳过的切片字节：算体积要带上，算速度必须刨掉（它们是从磁盘瞬间"完成"的）
     let cached_bytes = Arc::new(AtomicU64::new(0));
-    let results: Vec<Result<(), String>> = stream::iter(
-        playlist.segments.iter().cloned().enumerate().map(|(idx, seg_url)| {
-            let key_info = key_info.clone();
+    let jobs: Vec<(usize, String)> = indices
+        .iter()
+        .map(|&idx| (idx, playlist.segments[idx].clone()))
+        .collect();
+    let mut segments = stream::iter(
+        jobs.into_iter().map(|(idx, seg_url)| {
+            let key = key.clone();
             let done_count = Arc::clone(&done_count);
             let done_bytes = Arc::clone(&done_bytes);
             let cached_bytes = Arc::clone(&cached_bytes);
             let token = token.map(str::to_string);
-            let seg_path = parts_dir.join(segment_file_name(idx));
-            let tmp_path = parts_dir.join(format!("{}.tmp", segment_file_name(idx)));
             async move {
                 if cancellation_token.is_cancelled() {
                     return Err("下载已取消".to_string());
                 }
 
                 let mut from_cache = false;
-                let seg_len = match fs::metadata(&seg_path).await {
-                    // 已有完整切片（写入走 tmp+rename，存在即完整），跳过下载
-                    Ok(meta) if meta.len() > 0 => {
+                let seg_len = match cache.cached(idx).await {
+                    Some(bytes) => {
                         from_cache = true;
-                        meta.len()
+                        bytes.len() as u64
                     }
-                    _ => {
+                    None => {
                         let bytes = fetch_segment_with_retry(
                             &seg_url,
+                            mirrors,
                             token.as_deref(),
-                            key_info.as_deref(),
+                            key.as_deref(),
                             idx,
+                            limiter,
                             cancellation_token,
                         )
                         .await?;
-                        // 先写临时文件再改名，避免中断残留半个切片被误判为完整
-                        fs::write(&tmp_path, &bytes)
-                            .await
-                            .map_err(|e| format!("写入切片失败: {e}"))?;
-                        fs::rename(&tmp_path, &seg_path)
-                            .await
-                            .map_err(|e| format!("写入切片失败: {e}"))?;
+                        cache.store(idx, &bytes, ".tmp").await?;
                         bytes.len() as u64
                     }
                 };
@@ -391,27 +779,52 @@ pub(super) async fn download(
             }
         }),
     )
-    .buffer_unordered(SEGMENT_CONCURRENCY)
-    .collect()
-    .await;
+    .buffer_unordered(throttle::SEGMENT_CONCURRENCY_MAX);
+
+    // 普通失败让其余切片继续下完（多缓存一些，续传更省）；密钥错误时其余切片解出来
+    // 也是垃圾，立即停止
+    let mut first_error = None;
+    while let Some(result) = segments.next().await {
+        if let Err(e) = result {
+            if is_decryption_error(&e) {
+                return Err(e);
+            }
+            first_error.get_or_insert(e);
+        }
+    }
+    drop(segments);
+    log::debug!("切片下载结束时的并发: {}", limiter.limit());
+    // 无论成败都把已落盘切片记进清单，下次续传才认得
+    if let Err(e) = cache.flush().await {
+        log::warn!("{e}");
+    }
 
     if cancellation_token.is_cancelled() {
         return Err("下载已取消".to_string());
     }
-    for r in results {
-        r?;
+    if let Some(e) = first_error {
+        return Err(e);
     }
 
     // 5. 按序拼接切片 → .ts
     let ts_path = out_path.with_extension("ts");
-    assemble_ts(&ts_path, &parts_dir, total, cancellation_token).await?;
+    assemble_ts(&ts_path, cache, &indices, cancellation_token).await?;
 
     // ffmpeg 可用则 remux 成目标容器（通常 .mp4）；否则保留 .ts 并把原因带给用户，
     // 免得「配了 ffmpeg 结果还是 ts」看上去像是正常结果
     let mut warning = None;
     if out_path != ts_path {
-        match ffmpeg_path.filter(|p| !p.is_empty()) {
-            Some(ff) => match remux(ff, &ts_path, out_path).await {
+        match output.ffmpeg_path.as_deref().filter(|p| !p.is_empty()) {
+            Some(ff) => match remux_with_metadata(
+                ff,
+                &ts_path,
+                out_path,
+                &output.metadata,
+                trim.as_ref(),
+                &parts_dir,
+            )
+            .await
+            {
                 Ok(()) => {
                     let _ = fs::remove_file(&ts_path).await;
                     let _ = fs::remove_dir_all(&parts_dir).await;
@@ -438,16 +851,84 @@ pub(super) async fn download(
     Ok((ts_path, warning))
 }
 
-// 调 ffmpeg 把 .ts 无损转封装成目标容器（-c copy，不重新编码）
-async fn remux(ffmpeg: &str, ts_path: &Path, out_path: &Path) -> Result<(), String> {
-    let status = Command::new(ffmpeg)
-        .arg("-y")
-        .arg("-i")
-        .arg(ts_path)
-        .arg("-c")
-        .arg("copy")
-        .arg("-bsf:a")
-        .arg("aac_adtstoasc")
+// 封面拉取失败不影响视频本身，只是不嵌入。封面在公开桶，无需鉴权
+async fn fetch_cover_file(cover_url: &str, dir: &Path) -> Option<PathBuf> {
+    if cover_url.is_empty() {
+        return None;
+    }
+    let bytes = crate::http::get_bytes(cover_url)
+        .await
+        .inspect_err(|e| log::warn!("视频封面获取失败，不嵌入封面: {e}"))
+        .ok()?;
+    let ext = if bytes.starts_with(b"\x89PNG") { "png" } else { "jpg" };
+    let path = dir.join(format!("cover.{ext}"));
+    fs::write(&path, &bytes).await.ok()?;
+    Some(path)
+}
+
+// 带元数据与封面转封装；封面图格式异常等导致失败时，去掉封面再试一次
+async fn remux_with_metadata(
+    ffmpeg: &str,
+    ts_path: &Path,
+    out_path: &Path,
+    metadata: &VideoMetadata,
+    trim: Option<&Trim>,
+    work_dir: &Path,
+) -> Result<(), String> {
+    let Some(cover) = fetch_cover_file(&metadata.cover_url, work_dir).await else {
+        return remux(ffmpeg, ts_path, out_path, metadata, trim, None).await;
+    };
+    match remux(ffmpeg, ts_path, out_path, metadata, trim, Some(&cover)).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            log::warn!("嵌入封面转封装失败，去掉封面重试: {e}");
+            remux(ffmpeg, ts_path, out_path, metadata, trim, None).await
+        }
+    }
+}
+
+// 调 ffmpeg 把 .ts 无损转封装成目标容器（-c copy，不重新编码），同时写入元数据；
+// 有封面时作为第二路视频流以 attached_pic 嵌入。
+// 精确裁剪时起点多半不在关键帧上，音视频只能重新编码
+async fn remux(
+    ffmpeg: &str,
+    ts_path: &Path,
+    out_path: &Path,
+    metadata: &VideoMetadata,
+    trim: Option<&Trim>,
+    cover_path: Option<&Path>,
+) -> Result<(), String> {
+    let mut command = Command::new(ffmpeg);
+    command.arg("-y");
+    if let Some(trim) = trim {
+        // 放在 -i 之前只作用于 ts 输入，封面不受影响
+        command.arg("-ss").arg(format!("{:.3}", trim.offset.max(0.0)));
+    }
+    command.arg("-i").arg(ts_path);
+    if let Some(cover) = cover_path {
+        // ts 里可能带 mp4 装不下的数据流（如 timed_id3），只挑音视频
+        command
+            .arg("-i")
+            .arg(cover)
+            .args(["-map", "0:v", "-map", "0:a?", "-map", "1"]);
+    }
+    command.args(["-c", "copy"]);
+    match trim {
+        Some(trim) => {
+            if let Some(duration) = trim.duration {
+                command.arg("-t").arg(format!("{duration:.3}"));
+            }
+            command.args(["-c:v:0", "libx264", "-preset", "veryfast", "-crf", "20", "-c:a", "aac"]);
+        }
+        None => {
+            command.args(["-bsf:a", "aac_adtstoasc"]);
+        }
+    }
+    if cover_path.is_some() {
+        command.args(["-disposition:v:1", "attached_pic"]);
+    }
+    let status = command
+        .args(metadata.ffmpeg_args())
         .arg(out_path)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -473,3 +954,171 @@ pub async fn probe_ffmpeg(path: &str) -> bool {
         .map(|s| s.success())
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncryptMut;
+
+    fn encrypt(plain: &[u8], key: &KeyInfo) -> Vec<u8> {
+        let mut buf = plain.to_vec();
+        buf.resize(plain.len() + 16, 0);
+        cbc::Encryptor::<Aes128>::new(&key.key.into(), &key.iv.into())
+            .encrypt_padded_mut::<Pkcs7>(&mut buf, plain.len())
+            .unwrap()
+            .to_vec()
+    }
+
+    fn ts_packets(n: usize) -> Vec<u8> {
+        let mut data = vec![0xffu8; TS_PACKET_SIZE * n];
+        for packet in data.chunks_exact_mut(TS_PACKET_SIZE) {
+            packet[0] = TS_SYNC_BYTE;
+        }
+        data
+    }
+
+    #[test]
+    fn ts_sync_check() {
+        assert!(has_ts_sync(&ts_packets(3)));
+        let mut broken = ts_packets(3);
+        broken[TS_PACKET_SIZE] = 0;
+        assert!(!has_ts_sync(&broken));
+        assert!(!has_ts_sync(&[TS_SYNC_BYTE; 10]));
+    }
+
+    // 错误密钥解出的数据即使侥幸通过去填充，也过不了同步字节校验
+    #[test]
+    fn wrong_key_never_yields_ts() {
+        let right = KeyInfo {
+            key: [7u8; 16],
+            iv: [1u8; 16],
+        };
+        let wrong = KeyInfo {
+            key: [8u8; 16],
+            iv: [1u8; 16],
+        };
+        let cipher = encrypt(&ts_packets(4), &right);
+        assert!(has_ts_sync(&decrypt_segment(&cipher, &right).unwrap()));
+        assert!(!decrypt_segment(&cipher, &wrong).is_ok_and(|plain| has_ts_sync(&plain)));
+    }
+
+    #[test]
+    fn parses_segment_durations() {
+        let playlist = parse_playlist(
+            "#EXTM3U\n#EXTINF:10.0,\na.ts\n#EXTINF:8.5,title\nhttps://h/b.ts\nc.ts\n",
+            "https://h/v/index.m3u8",
+        );
+        assert_eq!(playlist.segments[0], "https://h/v/a.ts");
+        assert_eq!(playlist.durations, vec![10.0, 8.5, 0.0]);
+    }
+
+    // 切片 [0,10) [10,20) [20,30) [30,40)：12s–25s 只需要第 2、3 个切片
+    #[test]
+    fn selects_segments_overlapping_range() {
+        let durations = [10.0; 4];
+        let range = TimeRange {
+            start: 12.0,
+            end: Some(25.0),
+            precise: true,
+        };
+        let (indices, trim) = select_segments(&durations, &range).unwrap();
+        assert_eq!(indices, vec![1, 2]);
+        assert_eq!(trim.offset, 2.0);
+        assert_eq!(trim.duration, Some(13.0));
+
+        let open_ended = TimeRange {
+            start: 30.0,
+            end: None,
+            precise: false,
+        };
+        assert_eq!(select_segments(&durations, &open_ended).unwrap().0, vec![3]);
+
+        let beyond = TimeRange {
+            start: 50.0,
+            end: None,
+            precise: false,
+        };
+        assert!(select_segments(&durations, &beyond).is_err());
+        assert!(select_segments(&[0.0, 0.0], &range).is_err());
+    }
+
+    #[test]
+    fn spreads_segments_across_mirrors() {
+        let mirrors = Mirrors::new(
+            "https://r1/v/720/index.m3u8?sign=a",
+            &[
+                "https://r1/v/720/index.m3u8".to_string(),
+                "https://r2/v/720/index.m3u8".to_string(),
+            ],
+        );
+        assert_eq!(mirrors.len(), 2);
+        let seg = "https://r1/v/720/seg1.ts";
+        assert_eq!(mirrors.pick(seg, 0, 0).1, "https://r1/v/720/seg1.ts");
+        assert_eq!(mirrors.pick(seg, 1, 0).1, "https://r2/v/720/seg1.ts");
+        // 重试换到另一个镜像
+        assert_eq!(mirrors.pick(seg, 1, 1).1, "https://r1/v/720/seg1.ts");
+
+        // 连续失败的镜像不再分配
+        for _ in 0..MIRROR_FAILURE_LIMIT {
+            mirrors.record(1, false);
+        }
+        assert_eq!(mirrors.pick(seg, 1, 0).0, 0);
+
+        // 不在播放列表目录下的切片只用原地址
+        assert_eq!(mirrors.pick("https://other/x.ts", 1, 0).1, "https://other/x.ts");
+        assert_eq!(mirrors.candidates("https://other/x.ts"), 1);
+    }
+
+    // 对切片返回过鉴权错误的镜像不再选，哪怕剩下的镜像正处于暂停分配
+    #[test]
+    fn skips_mirrors_that_rejected_auth() {
+        let mirrors = Mirrors::new(
+            "https://r1/v/720/index.m3u8",
+            &[
+                "https://r2/v/720/index.m3u8".to_string(),
+                "https://r3/v/720/index.m3u8".to_string(),
+            ],
+        );
+        let seg = "https://r1/v/720/seg1.ts";
+        assert_eq!(mirrors.candidates(seg), 3);
+        assert_eq!(mirrors.pick_avoiding(seg, 1, 0, &[1]).0, 2);
+        assert_eq!(mirrors.pick_avoiding(seg, 1, 0, &[1, 2]).0, 0);
+
+        for _ in 0..MIRROR_FAILURE_LIMIT {
+            mirrors.record(0, false);
+        }
+        assert_eq!(mirrors.pick_avoiding(seg, 1, 0, &[1, 2]).0, 0);
+    }
+
+    #[test]
+    fn range_label() {
+        let range = TimeRange {
+            start: 300.0,
+            end: Some(3725.0),
+            precise: false,
+        };
+        assert_eq!(range.label(), "05m00s-1h02m05s");
+    }
+
+    #[test]
+    fn metadata_args_skip_empty_fields() {
+        let metadata = VideoMetadata {
+            title: "课堂实录".to_string(),
+            course_title: Some("有理数".to_string()),
+            category_path: vec!["初中".to_string(), " ".to_string(), "数学".to_string()],
+            source_id: "abc".to_string(),
+            source_url: String::new(),
+            cover_url: String::new(),
+        };
+        assert_eq!(
+            metadata.ffmpeg_args(),
+            vec![
+                "-metadata", "title=课堂实录",
+                "-metadata", "album=有理数",
+                "-metadata", "show=有理数",
+                "-metadata", "genre=初中 / 数学",
+                "-metadata", "episode_id=abc",
+            ]
+        );
+    }
+}
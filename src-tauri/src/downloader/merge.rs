@@ -0,0 +1,179 @@
+// 把同一课程的多个视频（片头、分段、小结…）按解析顺序无损拼接成一个 MP4，
+// 每个源资源一个章节，章节名取资源标题。
+//
+// 流程：ffmpeg -i 逐个探测时长 → 生成 concat 清单与 FFMETADATA 章节文件 →
+// concat demuxer + -c copy 拼接（不重新编码）。临时文件放在 <输出>.merge/ 下，结束即清理。
+
+use crate::models::CourseMergeInfo;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs;
+use tokio::process::Command;
+
+use super::task::{path_with_suffix, sanitize_name};
+
+// 读取 ffmpeg -i 输出里的 "Duration: 00:01:23.45"，返回毫秒
+fn parse_duration_ms(ffmpeg_stderr: &str) -> Option<u64> {
+    let rest = ffmpeg_stderr.split("Duration: ").nth(1)?;
+    let stamp = rest.split(',').next()?.trim();
+    let mut parts = stamp.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(((hours * 3600.0 + minutes * 60.0 + seconds) * 1000.0).round() as u64)
+}
+
+// ffmpeg 没有单独的 probe 模式，-i 不带输出会以非零码退出，时长在 stderr 里
+async fn probe_duration_ms(ffmpeg: &str, file: &Path) -> Result<u64, String> {
+    let output = Command::new(ffmpeg)
+        .arg("-hide_banner")
+        .arg("-i")
+        .arg(file)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|e| format!("无法执行 ffmpeg: {e}"))?;
+    parse_duration_ms(&String::from_utf8_lossy(&output.stderr))
+        .ok_or_else(|| format!("无法读取视频时长: {}", file.display()))
+}
+
+// concat 清单里路径用单引号包裹，单引号本身要写成 '\''
+fn concat_line(path: &Path) -> String {
+    format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+// FFMETADATA 中 = ; # \ 与换行需反斜杠转义
+fn escape_metadata(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// 章节按毫秒时间基写入：每段从上一段结束处开始
+fn chapters_metadata(course_title: &str, chapters: &[(String, u64)]) -> String {
+    let mut out = format!(";FFMETADATA1\ntitle={}\n", escape_metadata(course_title));
+    let mut start = 0u64;
+    for (title, duration) in chapters {
+        let end = start + duration;
+        out.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={start}\nEND={end}\ntitle={}\n",
+            escape_metadata(title)
+        ));
+        start = end;
+    }
+    out
+}
+
+// 合并输出默认放在第一个源文件旁边：「<课程名>（合并）.mp4」
+fn default_output_path(info: &CourseMergeInfo) -> Option<PathBuf> {
+    let dir = Path::new(&info.parts.first()?.file_path).parent()?;
+    Some(dir.join(format!("{}（合并）.mp4", sanitize_name(&info.course_title))))
+}
+
+/// 按 parts 顺序拼接视频并写入章节，返回输出文件路径
+pub(super) async fn merge_videos(info: &CourseMergeInfo, ffmpeg: &str) -> Result<PathBuf, String> {
+    if info.parts.len() < 2 {
+        return Err("至少需要两个已下载的视频才能合并".to_string());
+    }
+    let output = match info.output_path.as_deref().filter(|p| !p.is_empty()) {
+        Some(path) => PathBuf::from(path),
+        None => default_output_path(info).ok_or("无法确定合并输出路径")?,
+    };
+
+    let mut chapters = Vec::with_capacity(info.parts.len());
+    let mut concat_list = String::new();
+    for part in &info.parts {
+        let path = Path::new(&part.file_path);
+        if !path.exists() {
+            return Err(format!("文件不存在: {}", part.file_path));
+        }
+        let duration = probe_duration_ms(ffmpeg, path).await?;
+        chapters.push((part.title.clone(), duration));
+        concat_list.push_str(&concat_line(path));
+    }
+
+    let work_dir = path_with_suffix(&output, ".merge");
+    fs::create_dir_all(&work_dir)
+        .await
+        .map_err(|e| format!("创建临时目录失败: {e}"))?;
+    let result = async {
+        let list_path = work_dir.join("concat.txt");
+        let meta_path = work_dir.join("chapters.txt");
+        fs::write(&list_path, concat_list)
+            .await
+            .map_err(|e| format!("写入拼接清单失败: {e}"))?;
+        fs::write(&meta_path, chapters_metadata(&info.course_title, &chapters))
+            .await
+            .map_err(|e| format!("写入章节信息失败: {e}"))?;
+        concat(ffmpeg, &list_path, &meta_path, &output).await
+    }
+    .await;
+    let _ = fs::remove_dir_all(&work_dir).await;
+    result?;
+
+    log::info!("已合并 {} 个视频: {}", info.parts.len(), output.display());
+    Ok(output)
+}
+
+// 源文件可能是 .ts（未转封装）也可能是 .mp4，aac_adtstoasc 对两者都安全。
+// 转封装的 mp4 内嵌了封面（attached_pic 也是一路视频流），只取第一路视频
+async fn concat(ffmpeg: &str, list: &Path, meta: &Path, output: &Path) -> Result<(), String> {
+    let status = Command::new(ffmpeg)
+        .arg("-y")
+        .args(["-f", "concat", "-safe", "0", "-i"])
+        .arg(list)
+        .arg("-i")
+        .arg(meta)
+        .args(["-map", "0:v:0", "-map", "0:a?", "-map_metadata", "1", "-map_chapters", "1"])
+        .args(["-c", "copy", "-bsf:a", "aac_adtstoasc"])
+        .arg(output)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(|e| format!("无法执行 ffmpeg: {e}"))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        let _ = fs::remove_file(output).await;
+        Err(format!("ffmpeg 合并失败，退出码 {status}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ffmpeg_duration() {
+        let stderr = "Input #0, mpegts, from 'a.ts':\n  Duration: 00:01:23.45, start: 1.4, bitrate: 800 kb/s";
+        assert_eq!(parse_duration_ms(stderr), Some(83_450));
+        assert_eq!(parse_duration_ms("Duration: N/A, bitrate: N/A"), None);
+    }
+
+    #[test]
+    fn chapters_are_contiguous_and_escaped() {
+        let meta = chapters_metadata(
+            "有理数",
+            &[("片头".to_string(), 5_000), ("讲解=1".to_string(), 60_000)],
+        );
+        assert!(meta.starts_with(";FFMETADATA1\ntitle=有理数\n"));
+        assert!(meta.contains("START=0\nEND=5000\ntitle=片头\n"));
+        assert!(meta.contains("START=5000\nEND=65000\ntitle=讲解\\=1\n"));
+    }
+
+    #[test]
+    fn concat_line_escapes_single_quotes() {
+        assert_eq!(
+            concat_line(Path::new("/a/it's.mp4")),
+            "file '/a/it'\\''s.mp4'\n"
+        );
+    }
+}
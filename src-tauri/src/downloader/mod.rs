@@ -1,13 +1,21 @@
+mod library;
 pub mod m3u8;
+mod merge;
+mod parts;
+mod pdf_meta;
+mod preview;
+mod split;
 mod task;
+mod throttle;
 
-use crate::models::TextbookDownloadInfo;
+use crate::models::{CourseDownloadInfo, CourseMergeInfo, PdfSplitInfo, TextbookDownloadInfo};
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use tokio::fs;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
+pub(crate) use task::sanitize_name;
 use task::{DownloadEventEmitter, DownloadStatus};
 
 // 进行中的下载，以 URL 为键，用于取消/暂停（半成品保留，语义由前端决定）
@@ -65,13 +73,13 @@ pub async fn download_textbook(
 #[tauri::command]
 pub async fn download_course_resource(
     app_handle: tauri::AppHandle,
-    resource: crate::models::CourseDownloadInfo,
+    resource: CourseDownloadInfo,
     token: Option<String>,
     download_path: String,
     ffmpeg_path: Option<String>,
 ) -> Result<String, String> {
     let cancellation_token = CancellationToken::new();
-    let url = resource.download_url.clone();
+    let url = task::course_task_key(&resource).to_string();
 
     register_download_token(&url, cancellation_token.clone()).await;
 
@@ -89,6 +97,13 @@ pub async fn download_course_resource(
     result
 }
 
+/// 比对本地已下载的教材与平台当前版本，列出出版社已重新发布的
+/// （返回的 info 可原样交给 download_textbook 重新下载）
+#[tauri::command]
+pub async fn check_outdated_textbooks() -> Result<Vec<library::OutdatedTextbook>, String> {
+    library::find_outdated().await
+}
+
 /// 停止进行中的下载。半成品（.part / .parts）一律保留：
 /// 前端「暂停」直接复用本命令，「重新下载/删除」再调 remove_download_artifacts 清理。
 #[tauri::command]
@@ -103,13 +118,24 @@ pub async fn cancel_download(url: String) -> Result<(), String> {
 }
 
 /// 清理某任务的半成品（<final>.part 文件与 <final>.parts 切片目录），不动最终文件。
-/// file_path 为任务开始时事件上报的目标路径。
+/// file_path 为任务开始时事件上报的目标路径；没收到过该事件（如任务从未真正开始、
+/// 或上报前应用被关闭）的课程资源，可传 resource + download_path 按资源身份推算路径。
 #[tauri::command]
-pub async fn remove_download_artifacts(file_path: String) -> Result<(), String> {
-    if file_path.is_empty() {
-        return Ok(());
-    }
-    let final_path = std::path::PathBuf::from(&file_path);
+pub async fn remove_download_artifacts(
+    file_path: String,
+    resource: Option<CourseDownloadInfo>,
+    download_path: Option<String>,
+) -> Result<(), String> {
+    let final_path = if !file_path.is_empty() {
+        std::path::PathBuf::from(&file_path)
+    } else {
+        match (resource, download_path.filter(|p| !p.is_empty())) {
+            (Some(resource), Some(download_path)) => {
+                task::course_save_path(&resource, &download_path)
+            }
+            _ => return Ok(()),
+        }
+    };
 
     let part = task::path_with_suffix(&final_path, ".part");
     if fs::try_exists(&part).await.unwrap_or(false) {
@@ -128,6 +154,63 @@ pub async fn remove_download_artifacts(file_path: String) -> Result<(), String>
     Ok(())
 }
 
+/// 把同一课程已下载的多个视频按顺序合并为一个带章节的 MP4，返回输出路径。
+/// 前端可在课程视频全部下载完成后调用，也可对早先下载好的文件单独调用。
+#[tauri::command]
+pub async fn merge_course_videos(
+    info: CourseMergeInfo,
+    ffmpeg_path: Option<String>,
+) -> Result<String, String> {
+    let ffmpeg = ffmpeg_path
+        .filter(|p| !p.is_empty())
+        .ok_or("合并视频需要 ffmpeg，请在「设置」中指定 ffmpeg 路径")?;
+    let output = merge::merge_videos(&info, &ffmpeg).await?;
+    Ok(output.to_string_lossy().into_owned())
+}
+
+/// 把已下载的教材 PDF 按页码范围或已有书签拆成多个文件（原文件保留），返回输出路径
+#[tauri::command]
+pub async fn split_textbook_pdf(info: PdfSplitInfo) -> Result<Vec<String>, String> {
+    let outputs = split::split_pdf(info).await?;
+    Ok(outputs
+        .into_iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// 开始预览课程视频：在本机起 HLS 服务现场解密切片，返回可交给播放器的 m3u8 地址。
+/// 预览拉到的切片写入该资源的 .parts 缓存，之后正式下载会直接复用。
+#[tauri::command]
+pub async fn start_video_preview(
+    resource: CourseDownloadInfo,
+    token: Option<String>,
+    download_path: String,
+) -> Result<String, String> {
+    if !resource.is_video {
+        return Err("只有视频资源支持预览".to_string());
+    }
+    if download_path.is_empty() {
+        return Err("未设置下载路径".to_string());
+    }
+    let range = task::course_time_range(&resource)?;
+    let save_path = task::course_save_path(&resource, &download_path);
+    preview::start(
+        &resource.download_url,
+        &resource.mirror_urls,
+        token.as_deref(),
+        &save_path,
+        range.as_ref(),
+    )
+    .await
+}
+
+/// 结束预览（关闭播放器时调用），参数为 start_video_preview 返回的地址
+#[tauri::command]
+pub async fn stop_video_preview(preview_url: String) -> Result<(), String> {
+    preview::stop(&preview_url).await;
+    Ok(())
+}
+
 /// 检测 ffmpeg 是否可用（设置页用）
 #[tauri::command]
 pub async fn check_ffmpeg(path: String) -> Result<bool, String> {
@@ -0,0 +1,334 @@
+// m3u8 视频的切片缓存：<最终名>.parts/ 目录下按播放列表序号存放解密后的切片，
+// 外加一份结构化清单 manifest.json，记录播放列表身份（切片地址、KEY 地址、IV）
+// 以及每个已落盘切片的字节数与 md5。
+//
+// 续传时：
+// - 播放列表身份按「去掉查询串」的地址比较。平台的签名参数每次会话都会变，
+//   只要路径、切片数、KEY 与 IV 不变，就视为同一视频，沿用缓存并更新清单里的地址
+// - 已落盘切片必须与清单记录的长度与 md5 一致才算完整，否则删掉重下；
+//   清单里没有记录的切片文件（写完切片、清单还没来得及落盘就中断）同样重下
+//
+// 正式下载与预览可能同时读写同一目录，故同一目录只保留一个 PartsCache 实例（OPEN_CACHES）。
+
+use md5::{Digest, Md5};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::fs;
+
+use super::m3u8::{Playlist, TimeRange};
+
+const MANIFEST_FILE: &str = "manifest.json";
+// 旧版本只记地址与切片数的纯文本清单，无法校验切片，遇到即整目录重建
+const LEGACY_MANIFEST_FILE: &str = "manifest.txt";
+// 每落盘这么多切片写一次清单；中断时没记进清单的切片只是多下一遍
+const FLUSH_EVERY: usize = 16;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SegmentEntry {
+    url: String,
+    // 解密后的字节数与 md5；未下载时为 0 / 空
+    #[serde(default)]
+    len: u64,
+    #[serde(default)]
+    md5: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Manifest {
+    playlist_url: String,
+    #[serde(default)]
+    key_uri: Option<String>,
+    #[serde(default)]
+    iv: Option<String>,
+    // 时间段下载的区间标签；切片按序号存放，换区间也能复用重叠部分，这里只作记录
+    #[serde(default)]
+    range: Option<String>,
+    segments: Vec<SegmentEntry>,
+}
+
+impl Manifest {
+    fn new(playlist_url: &str, playlist: &Playlist, range: Option<&TimeRange>) -> Self {
+        Self {
+            playlist_url: playlist_url.to_string(),
+            key_uri: playlist.key_url.clone(),
+            iv: playlist.iv_hex.clone(),
+            range: range.map(TimeRange::label),
+            segments: playlist
+                .segments
+                .iter()
+                .map(|url| SegmentEntry {
+                    url: url.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    // 签名地址会变，内容不变：比较去掉查询串后的切片与 KEY 地址、IV
+    fn same_content(&self, other: &Manifest) -> bool {
+        self.segments.len() == other.segments.len()
+            && self.key_uri.as_deref().map(strip_query) == other.key_uri.as_deref().map(strip_query)
+            && self.iv.as_deref().map(str::to_ascii_lowercase)
+                == other.iv.as_deref().map(str::to_ascii_lowercase)
+            && self
+                .segments
+                .iter()
+                .zip(&other.segments)
+                .all(|(a, b)| strip_query(&a.url) == strip_query(&b.url))
+    }
+}
+
+fn strip_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+fn md5_hex(bytes: &[u8]) -> String {
+    hex::encode(Md5::digest(bytes))
+}
+
+fn segment_file_name(idx: usize) -> String {
+    format!("seg_{idx:05}.ts")
+}
+
+struct CacheState {
+    manifest: Manifest,
+    // 自上次写清单以来新落盘的切片数
+    unflushed: usize,
+}
+
+pub(super) struct PartsCache {
+    dir: PathBuf,
+    state: Mutex<CacheState>,
+    // 串行化清单写入，避免并发 flush 交错写坏文件
+    flush_lock: tokio::sync::Mutex<()>,
+}
+
+static OPEN_CACHES: Lazy<Mutex<HashMap<PathBuf, Weak<PartsCache>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+impl PartsCache {
+    /// 打开（必要时新建或重建）切片缓存目录并写入当前清单。
+    /// 同一目录已有打开的实例且内容一致时直接共用
+    pub(super) async fn open(
+        dir: &Path,
+        playlist_url: &str,
+        playlist: &Playlist,
+        range: Option<&TimeRange>,
+    ) -> Result<Arc<Self>, String> {
+        let fresh = Manifest::new(playlist_url, playlist, range);
+
+        let shared = OPEN_CACHES
+            .lock()
+            .unwrap()
+            .get(dir)
+            .and_then(Weak::upgrade);
+        if let Some(cache) = shared {
+            if cache.state.lock().unwrap().manifest.same_content(&fresh) {
+                cache.adopt(fresh);
+                cache.flush().await?;
+                return Ok(cache);
+            }
+        }
+
+        let previous = read_manifest(dir).await;
+        let manifest = match previous {
+            Some(prev) if prev.same_content(&fresh) => {
+                let mut merged = fresh;
+                for (entry, old) in merged.segments.iter_mut().zip(prev.segments) {
+                    entry.len = old.len;
+                    entry.md5 = old.md5;
+                }
+                merged
+            }
+            _ => {
+                if fs::try_exists(dir).await.unwrap_or(false) {
+                    log::info!("播放列表已变化，重建切片缓存: {}", dir.display());
+                    fs::remove_dir_all(dir)
+                        .await
+                        .map_err(|e| format!("清理切片缓存失败: {e}"))?;
+                }
+                fresh
+            }
+        };
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("创建切片缓存目录失败: {e}"))?;
+
+        let cache = Arc::new(Self {
+            dir: dir.to_path_buf(),
+            state: Mutex::new(CacheState {
+                manifest,
+                unflushed: 0,
+            }),
+            flush_lock: tokio::sync::Mutex::new(()),
+        });
+        cache.flush().await?;
+        OPEN_CACHES
+            .lock()
+            .unwrap()
+            .insert(dir.to_path_buf(), Arc::downgrade(&cache));
+        Ok(cache)
+    }
+
+    // 沿用已记录的切片，换成最新（签名）地址
+    fn adopt(&self, fresh: Manifest) {
+        let mut state = self.state.lock().unwrap();
+        let manifest = &mut state.manifest;
+        for (entry, new) in manifest.segments.iter_mut().zip(fresh.segments) {
+            entry.url = new.url;
+        }
+        manifest.playlist_url = fresh.playlist_url;
+        manifest.key_uri = fresh.key_uri;
+        manifest.range = fresh.range.or(manifest.range.take());
+    }
+
+    pub(super) fn segment_path(&self, idx: usize) -> PathBuf {
+        self.dir.join(segment_file_name(idx))
+    }
+
+    /// 读取已缓存的切片；与清单记录不符（长度或 md5）的切片会被删除并返回 None
+    pub(super) async fn cached(&self, idx: usize) -> Option<Vec<u8>> {
+        let (len, md5) = {
+            let state = self.state.lock().unwrap();
+            let entry = state.manifest.segments.get(idx)?;
+            (entry.len, entry.md5.clone())
+        };
+        if len == 0 || md5.is_empty() {
+            // 没有记录：可能是写完切片、清单未落盘就中断，不可信。文件不删——预览与下载共用缓存，
+            // 另一方可能刚改名到位、正要记入清单；重新下载时 store 会覆盖它
+            return None;
+        }
+        let path = self.segment_path(idx);
+        let bytes = fs::read(&path).await.ok()?;
+        if bytes.len() as u64 == len && md5_hex(&bytes) == md5 {
+            return Some(bytes);
+        }
+        log::warn!("切片 {idx} 与清单记录不符，重新下载");
+        let _ = fs::remove_file(&path).await;
+        self.forget(idx);
+        None
+    }
+
+    fn forget(&self, idx: usize) {
+        if let Some(entry) = self.state.lock().unwrap().manifest.segments.get_mut(idx) {
+            entry.len = 0;
+            entry.md5.clear();
+        }
+    }
+
+    /// 落盘一个解密后的切片并记入清单。tmp_suffix 区分不同写入方的临时文件，
+    /// 先写临时文件再改名，避免中断残留半个切片
+    pub(super) async fn store(
+        &self,
+        idx: usize,
+        bytes: &[u8],
+        tmp_suffix: &str,
+    ) -> Result<(), String> {
+        let path = self.segment_path(idx);
+        let tmp_path = self.dir.join(format!("{}{tmp_suffix}", segment_file_name(idx)));
+        fs::write(&tmp_path, bytes)
+            .await
+            .map_err(|e| format!("写入切片失败: {e}"))?;
+        fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| format!("写入切片失败: {e}"))?;
+
+        let should_flush = {
+            let mut state = self.state.lock().unwrap();
+            let Some(entry) = state.manifest.segments.get_mut(idx) else {
+                return Err(format!("切片 {idx} 不存在"));
+            };
+            entry.len = bytes.len() as u64;
+            entry.md5 = md5_hex(bytes);
+            state.unflushed += 1;
+            state.unflushed >= FLUSH_EVERY
+        };
+        if should_flush {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// 把清单写回磁盘（临时文件 + 改名）
+    pub(super) async fn flush(&self) -> Result<(), String> {
+        let _guard = self.flush_lock.lock().await;
+        let json = {
+            let mut state = self.state.lock().unwrap();
+            state.unflushed = 0;
+            serde_json::to_vec_pretty(&state.manifest).map_err(|e| format!("序列化切片清单失败: {e}"))?
+        };
+        let tmp_path = self.dir.join(format!("{MANIFEST_FILE}.tmp"));
+        fs::write(&tmp_path, json)
+            .await
+            .map_err(|e| format!("写入切片清单失败: {e}"))?;
+        fs::rename(&tmp_path, self.dir.join(MANIFEST_FILE))
+            .await
+            .map_err(|e| format!("写入切片清单失败: {e}"))
+    }
+}
+
+async fn read_manifest(dir: &Path) -> Option<Manifest> {
+    if fs::try_exists(dir.join(LEGACY_MANIFEST_FILE)).await.unwrap_or(false) {
+        return None;
+    }
+    let bytes = fs::read(dir.join(MANIFEST_FILE)).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(query: &str, iv: &str) -> Playlist {
+        Playlist {
+            segments: vec![
+                format!("https://cdn/v/a.ts?{query}"),
+                format!("https://cdn/v/b.ts?{query}"),
+            ],
+            durations: vec![10.0, 10.0],
+            key_url: Some(format!("https://k/keys/1?{query}")),
+            iv_hex: Some(iv.to_string()),
+        }
+    }
+
+    #[test]
+    fn re_signed_playlist_is_the_same_content() {
+        let old = Manifest::new("u", &playlist("sign=1", "0xAB"), None);
+        let new = Manifest::new("u", &playlist("sign=2", "0xab"), None);
+        assert!(old.same_content(&new));
+
+        let other_iv = Manifest::new("u", &playlist("sign=2", "0xcd"), None);
+        assert!(!old.same_content(&other_iv));
+    }
+
+    #[tokio::test]
+    async fn corrupted_segments_are_rejected_on_resume() {
+        let temp = crate::storage::TestDir::new("parts-test");
+        let dir = temp.path();
+
+        let cache = PartsCache::open(dir, "u", &playlist("sign=1", "0x00"), None)
+            .await
+            .unwrap();
+        cache.store(0, b"segment zero", ".tmp").await.unwrap();
+        cache.store(1, b"segment one", ".tmp").await.unwrap();
+        cache.flush().await.unwrap();
+        drop(cache);
+
+        // 换了签名重新打开：缓存沿用；切片 1 被篡改，应被丢弃
+        fs::write(dir.join(segment_file_name(1)), b"segment 0ne").await.unwrap();
+        let cache = PartsCache::open(dir, "u", &playlist("sign=2", "0x00"), None)
+            .await
+            .unwrap();
+        assert_eq!(cache.cached(0).await.as_deref(), Some(&b"segment zero"[..]));
+        assert_eq!(cache.cached(1).await, None);
+        assert!(!cache.segment_path(1).exists());
+
+        // 没有记录的文件只是不用，不删：另一方可能正要把它记入清单
+        fs::write(cache.segment_path(1), b"segment one").await.unwrap();
+        assert_eq!(cache.cached(1).await, None);
+        assert!(cache.segment_path(1).exists());
+    }
+}
@@ -0,0 +1,131 @@
+// 下载完成的教材 PDF 补写文档信息：平台的 PDF 没有 Info，阅读器与文件管理里只显示文件名。
+// Info 写书名、分类（Subject）、资源 id（Keywords）。
+// 章节书签暂不生成：章节树里没有可靠的 PDF 页码（印刷页码与 PDF 页序还差着封面、前言）。
+
+use lopdf::{Dictionary, Document, Object, text_string};
+#[cfg(test)]
+use lopdf::dictionary;
+use std::path::Path;
+
+pub(super) struct PdfMeta {
+    pub title: String,
+    pub subject: String,
+    pub keywords: String,
+}
+
+fn set_info_fields(info: &mut Dictionary, meta: &PdfMeta) {
+    for (key, value) in [
+        ("Title", &meta.title),
+        ("Subject", &meta.subject),
+        ("Keywords", &meta.keywords),
+    ] {
+        if !value.is_empty() {
+            info.set(key, text_string(value));
+        }
+    }
+}
+
+pub(super) fn set_info(doc: &mut Document, meta: &PdfMeta) -> Result<(), String> {
+    let info_id = match doc.trailer.get_mut(b"Info") {
+        Ok(Object::Reference(id)) => *id,
+        Ok(Object::Dictionary(info)) => {
+            set_info_fields(info, meta);
+            return Ok(());
+        }
+        _ => {
+            let id = doc.add_object(Dictionary::new());
+            doc.trailer.set("Info", id);
+            id
+        }
+    };
+    let info = doc
+        .get_object_mut(info_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(|e| format!("PDF 文档信息格式异常: {e}"))?;
+    set_info_fields(info, meta);
+    Ok(())
+}
+
+fn write_blocking(path: &Path, meta: &PdfMeta) -> Result<(), String> {
+    let mut doc = Document::load(path).map_err(|e| format!("解析 PDF 失败: {e}"))?;
+    if doc.is_encrypted() {
+        return Err("PDF 已加密，不写元数据".to_string());
+    }
+    set_info(&mut doc, meta)?;
+
+    // 先写临时文件再替换，中途失败不会弄坏已下载的文件
+    let tmp_path = path.with_extension("pdf.meta");
+    if let Err(e) = doc.save(&tmp_path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(format!("保存 PDF 失败: {e}"));
+    }
+    std::fs::rename(&tmp_path, path).map_err(|e| format!("替换 PDF 失败: {e}"))?;
+    log::info!("已写入 PDF 元数据: {}", path.display());
+    Ok(())
+}
+
+/// 给下载好的教材 PDF 写入文档信息（在阻塞线程里解析整份 PDF）
+pub(super) async fn write(path: &Path, meta: PdfMeta) -> Result<(), String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || write_blocking(&path, &meta))
+        .await
+        .map_err(|e| format!("写入任务异常: {e}"))?
+}
+
+// 测试用：只有页面树、没有内容的 PDF
+#[cfg(test)]
+pub(super) fn blank_pdf(page_count: usize) -> Document {
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let kids: Vec<Object> = (0..page_count)
+        .map(|_| {
+            doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            })
+            .into()
+        })
+        .collect();
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_count as i64,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::decode_text_string;
+
+    #[test]
+    fn writes_info() {
+        let mut doc = blank_pdf(3);
+        let meta = PdfMeta {
+            title: "语文 七年级上册".to_string(),
+            subject: "初中 / 语文".to_string(),
+            keywords: String::new(),
+        };
+        set_info(&mut doc, &meta).unwrap();
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        let doc = Document::load_mem(&bytes).unwrap();
+        let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        let info = doc.get_dictionary(info_id).unwrap();
+        let title = decode_text_string(info.get(b"Title").unwrap()).unwrap();
+        assert_eq!(title, "语文 七年级上册");
+        // 空字段不写
+        assert!(!info.has(b"Keywords"));
+    }
+}
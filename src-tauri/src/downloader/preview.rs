@@ -0,0 +1,311 @@
+// 下载前的视频预览：webview 没法直接播放平台的加密 HLS（密钥是自定义两段式握手，
+// 请求还要带 x-nd-auth 头），这里在 127.0.0.1 上起一个极简 HTTP 服务：
+//   GET /{session}/index.m3u8   改写后的播放列表（去掉 EXT-X-KEY，切片指向本服务）
+//   GET /{session}/seg/{n}.ts   现场拉取 + 解密第 n 个切片
+// 预览拉到的切片同时写进该资源的 <最终名>.parts/ 缓存，之后正式下载可直接复用。
+
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use super::m3u8::{self, Mirrors, Playlist, SegmentKey};
+use super::parts::PartsCache;
+use super::throttle::AdaptiveLimiter;
+
+// 请求头上限：只处理简单 GET，超出即视为异常请求
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+struct PreviewSession {
+    playlist_content: String,
+    playlist: Playlist,
+    key: Option<SegmentKey>,
+    mirrors: Mirrors,
+    token: Option<String>,
+    // 与正式下载共用的切片缓存
+    cache: Arc<PartsCache>,
+    limiter: AdaptiveLimiter,
+    // 停止预览时取消在途的切片请求
+    cancellation_token: CancellationToken,
+}
+
+static SERVER_ADDR: OnceCell<SocketAddr> = OnceCell::new();
+static SESSIONS: Lazy<Mutex<HashMap<String, Arc<PreviewSession>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static SESSION_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// 会话 id 出现在本机 URL 里，别的本地进程也能访问 127.0.0.1，故不用可猜的自增序号
+fn new_session_id(m3u8_url: &str) -> String {
+    use md5::{Digest, Md5};
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let seq = SESSION_SEQ.fetch_add(1, Ordering::SeqCst);
+    let mut hasher = Md5::new();
+    hasher.update(format!("{nanos}:{seq}:{m3u8_url}").as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+async fn ensure_server() -> Result<SocketAddr, String> {
+    if let Some(addr) = SERVER_ADDR.get() {
+        return Ok(*addr);
+    }
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| format!("启动预览服务失败: {e}"))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| format!("启动预览服务失败: {e}"))?;
+    // 并发首启时只保留第一个监听器，后来者直接丢弃
+    if SERVER_ADDR.set(addr).is_err() {
+        return Ok(*SERVER_ADDR.get().expect("已设置"));
+    }
+    log::info!("视频预览服务已启动: http://{addr}");
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream));
+                }
+                Err(e) => log::warn!("预览服务接受连接失败: {e}"),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+/// 建立预览会话，返回本机播放地址。range 与正式下载一致，只用于校验切片缓存清单，
+/// 预览本身总是播放整段
+pub(super) async fn start(
+    m3u8_url: &str,
+    mirror_urls: &[String],
+    token: Option<&str>,
+    save_path: &std::path::Path,
+    range: Option<&m3u8::TimeRange>,
+) -> Result<String, String> {
+    let content = m3u8::get_text_authed(m3u8_url, token)
+        .await
+        .map_err(|e| format!("获取播放列表失败: {e}"))?;
+    let playlist = m3u8::parse_playlist(&content, m3u8_url);
+    if playlist.segments.is_empty() {
+        return Err("播放列表为空".to_string());
+    }
+    let key = match playlist.key_url.as_deref() {
+        Some(ku) => Some(SegmentKey::handshake(ku, playlist.iv_hex.as_deref(), token).await?),
+        None => None,
+    };
+
+    let parts_dir = super::task::path_with_suffix(save_path, ".parts");
+    if let Some(parent) = parts_dir.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建下载目录失败: {e}"))?;
+    }
+    let cache = PartsCache::open(&parts_dir, m3u8_url, &playlist, range).await?;
+
+    let addr = ensure_server().await?;
+    let id = new_session_id(m3u8_url);
+    SESSIONS.lock().await.insert(
+        id.clone(),
+        Arc::new(PreviewSession {
+            playlist_content: content,
+            playlist,
+            key,
+            mirrors: Mirrors::new(m3u8_url, mirror_urls),
+            token: token.map(str::to_string),
+            cache,
+            limiter: AdaptiveLimiter::new(),
+            cancellation_token: CancellationToken::new(),
+        }),
+    );
+    Ok(format!("http://{addr}/{id}/index.m3u8"))
+}
+
+/// 结束预览会话（参数为 start 返回的地址）；在途切片请求随之取消
+pub(super) async fn stop(preview_url: &str) {
+    let Some(id) = preview_url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split('/').nth(1))
+    else {
+        return;
+    };
+    if let Some(session) = SESSIONS.lock().await.remove(id) {
+        session.cancellation_token.cancel();
+        if let Err(e) = session.cache.flush().await {
+            log::debug!("预览结束时写入切片清单失败: {e}");
+        }
+    }
+}
+
+// 改写播放列表：切片行按出现顺序换成本服务的相对地址（与 parse_playlist 的编号一致），
+// 加密声明去掉（本服务返回的已是明文）
+fn rewrite_playlist(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut idx = 0usize;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("#EXT-X-KEY") {
+            continue;
+        }
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            out.push_str(&format!("seg/{idx}.ts\n"));
+            idx += 1;
+        } else {
+            out.push_str(trimmed);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// 先查切片缓存（正式下载或之前的预览已拉过），没有再现场拉取并写回缓存
+async fn serve_segment(session: &PreviewSession, idx: usize) -> Result<Vec<u8>, String> {
+    if let Some(bytes) = session.cache.cached(idx).await {
+        return Ok(bytes);
+    }
+
+    let seg_url = session
+        .playlist
+        .segments
+        .get(idx)
+        .ok_or_else(|| format!("切片 {idx} 不存在"))?;
+    let bytes = m3u8::fetch_segment_with_retry(
+        seg_url,
+        &session.mirrors,
+        session.token.as_deref(),
+        session.key.as_ref(),
+        idx,
+        &session.limiter,
+        &session.cancellation_token,
+    )
+    .await?;
+
+    // 临时文件名与正式下载区分开，两边同时写同一切片也不会互相踩
+    if let Err(e) = session.cache.store(idx, &bytes, ".preview.tmp").await {
+        log::debug!("预览切片写入缓存失败: {e}");
+    }
+    Ok(bytes)
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: message.as_bytes().to_vec(),
+        }
+    }
+}
+
+async fn route(path: &str) -> Response {
+    let mut parts = path.trim_start_matches('/').splitn(2, '/');
+    let (Some(id), Some(rest)) = (parts.next(), parts.next()) else {
+        return Response::error("404 Not Found", "not found");
+    };
+    let Some(session) = SESSIONS.lock().await.get(id).cloned() else {
+        return Response::error("404 Not Found", "预览会话不存在或已结束");
+    };
+
+    if rest == "index.m3u8" {
+        return Response::ok(
+            "application/vnd.apple.mpegurl",
+            rewrite_playlist(&session.playlist_content).into_bytes(),
+        );
+    }
+    let Some(idx) = rest
+        .strip_prefix("seg/")
+        .and_then(|name| name.strip_suffix(".ts"))
+        .and_then(|n| n.parse::<usize>().ok())
+    else {
+        return Response::error("404 Not Found", "not found");
+    };
+    match serve_segment(&session, idx).await {
+        Ok(bytes) => Response::ok("video/mp2t", bytes),
+        Err(e) => {
+            log::warn!("预览切片 {idx} 获取失败: {e}");
+            Response::error("502 Bad Gateway", &e)
+        }
+    }
+}
+
+// 只支持 GET / OPTIONS，每个连接处理一个请求后关闭；CORS 全开，供 webview 里的播放器跨源拉取
+async fn handle_connection(mut stream: TcpStream) {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+        }
+        if head.len() > MAX_REQUEST_HEAD {
+            return;
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("/");
+    let path = path.split('?').next().unwrap_or(path);
+
+    let response = match method {
+        "GET" => route(path).await,
+        "OPTIONS" => Response {
+            status: "204 No Content",
+            content_type: "text/plain",
+            body: Vec::new(),
+        },
+        _ => Response::error("405 Method Not Allowed", "method not allowed"),
+    };
+
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Headers: *\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    let _ = stream.write_all(header.as_bytes()).await;
+    let _ = stream.write_all(&response.body).await;
+    let _ = stream.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites_segments_and_drops_key() {
+        let content = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"https://k/keys/1\",IV=0x00\n\
+            #EXTINF:10.0,\nhttps://h/a/seg0.ts\n#EXTINF:8.5,\nseg1.ts\n#EXT-X-ENDLIST\n";
+        assert_eq!(
+            rewrite_playlist(content),
+            "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10.0,\nseg/0.ts\n\
+             #EXTINF:8.5,\nseg/1.ts\n#EXT-X-ENDLIST\n"
+        );
+    }
+}
@@ -0,0 +1,479 @@
+// 把下载好的教材 PDF 拆成按单元/页码段的小文件，老师只带本周要讲的部分。
+// 拆分方式：前端给出页码段，或按 PDF 自带的书签拆。
+// 原文件保留；输出放在原文件旁「<书名>（拆分）」目录，文件名取章节标题并加序号保证顺序。
+//
+// 每段的做法：复制整份文档 → 页面树压平成只含本段页面 → 去掉书签、命名目标、结构树、表单
+// 等指向其他页面的入口，以及跳到段外页面的链接注释 → 清理不再被引用的对象后保存。
+// 页面从上级页面树继承的属性先补到页面自身上。只要还有一条引用链通到段外页面，
+// 那些页面连同内容流就都会留在输出里，每个小文件都和整本书差不多大。
+
+use super::pdf_meta::{self, PdfMeta};
+use super::task::sanitize_name;
+use crate::models::{PdfSplitInfo, PdfSplitRange};
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// 页面可从父节点继承的属性
+const INHERITABLE: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+// 目录里会引用到全书页面的条目：书签、页码标签、打开动作、命名目标、结构树、表单、文章线程
+const CATALOG_PAGE_REFS: [&[u8]; 9] = [
+    b"Outlines",
+    b"PageLabels",
+    b"OpenAction",
+    b"PageMode",
+    b"Names",
+    b"Dests",
+    b"StructTreeRoot",
+    b"AcroForm",
+    b"Threads",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    title: String,
+    // 从 1 起，首尾都含
+    start: u32,
+    end: u32,
+}
+
+fn segments_from_ranges(ranges: &[PdfSplitRange], page_count: u32) -> Result<Vec<Segment>, String> {
+    ranges
+        .iter()
+        .map(|range| {
+            if range.start_page == 0 || range.start_page > range.end_page {
+                return Err(format!(
+                    "无效的页码范围: {}-{}",
+                    range.start_page, range.end_page
+                ));
+            }
+            if range.end_page > page_count {
+                return Err(format!(
+                    "页码 {} 超出总页数 {page_count}",
+                    range.end_page
+                ));
+            }
+            let title = Some(range.title.trim())
+                .filter(|title| !title.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("第{}-{}页", range.start_page, range.end_page));
+            Ok(Segment {
+                title,
+                start: range.start_page,
+                end: range.end_page,
+            })
+        })
+        .collect()
+}
+
+// 按书签拆：取至少有两条书签的最浅一级（只有一条书名根书签时自动下探一级），
+// 每条到下一条的前一页为一段，最后一段到全书末尾；第一条之前的封面、前言不单独输出
+fn segments_from_toc(entries: &[(usize, String, u32)], page_count: u32) -> Vec<Segment> {
+    let Some(level) = (1..=entries.iter().map(|e| e.0).max().unwrap_or(0))
+        .find(|&level| entries.iter().filter(|e| e.0 == level).count() >= 2)
+    else {
+        return Vec::new();
+    };
+    let mut starts: Vec<(u32, &str)> = entries
+        .iter()
+        .filter(|e| e.0 == level && e.2 >= 1 && e.2 <= page_count)
+        .map(|e| (e.2, e.1.as_str()))
+        .collect();
+    starts.sort_by_key(|(page, _)| *page);
+    // 同页起始的几条书签只保留第一条
+    starts.dedup_by_key(|(page, _)| *page);
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, (start, title))| Segment {
+            title: title.trim().to_string(),
+            start: *start,
+            end: starts.get(i + 1).map_or(page_count, |(next, _)| next - 1),
+        })
+        .collect()
+}
+
+fn outline_entries(doc: &Document) -> Vec<(usize, String, u32)> {
+    match doc.get_toc() {
+        Ok(toc) => toc
+            .toc
+            .into_iter()
+            .map(|entry| (entry.level, entry.title, entry.page as u32))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// 把继承来的属性补到页面自身上，之后改挂到根页面树下也不丢
+fn materialize_inherited(doc: &mut Document, page_id: ObjectId) {
+    let mut inherited = Vec::new();
+    let Ok(page) = doc.get_dictionary(page_id) else {
+        return;
+    };
+    let mut missing: Vec<&[u8]> = INHERITABLE
+        .iter()
+        .copied()
+        .filter(|key| !page.has(key))
+        .collect();
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    while let Some(parent_id) = parent.filter(|_| !missing.is_empty()) {
+        let Ok(node) = doc.get_dictionary(parent_id) else {
+            break;
+        };
+        missing.retain(|key| match node.get(key) {
+            Ok(value) => {
+                inherited.push((key.to_vec(), value.clone()));
+                false
+            }
+            Err(_) => true,
+        });
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+    }
+    if let Ok(page) = doc.get_object_mut(page_id).and_then(Object::as_dict_mut) {
+        for (key, value) in inherited {
+            page.set(key, value);
+        }
+    }
+}
+
+// 链接注释的跳转目标
+enum LinkTarget {
+    // 不是文档内跳转（普通注释、网址链接等）
+    None,
+    // 跳到本文档的某一页
+    Page(ObjectId),
+    // 命名目标：Names/Dests 去掉后无从解析
+    Named,
+}
+
+fn link_target(doc: &Document, annot: &Dictionary) -> LinkTarget {
+    let action_dest = || {
+        let action = doc
+            .dereference(annot.get(b"A").ok()?)
+            .ok()?
+            .1
+            .as_dict()
+            .ok()?;
+        (action.get(b"S").and_then(Object::as_name).ok()? == b"GoTo")
+            .then(|| action.get(b"D").ok())
+            .flatten()
+    };
+    let Some(dest) = annot.get(b"Dest").ok().or_else(action_dest) else {
+        return LinkTarget::None;
+    };
+    match doc.dereference(dest).map(|(_, dest)| dest) {
+        Ok(Object::Array(items)) => match items.first() {
+            Some(Object::Reference(page_id)) => LinkTarget::Page(*page_id),
+            _ => LinkTarget::None,
+        },
+        Ok(Object::Name(_) | Object::String(..)) => LinkTarget::Named,
+        _ => LinkTarget::None,
+    }
+}
+
+// 去掉跳到段外页面（或已失效的命名目标）的注释；留下的注释摘掉可选的 /P（所在页）
+// 与表单控件的 /Parent（字段树会串到其他页面的控件）
+fn prune_annotations(doc: &mut Document, page_ids: &[ObjectId]) {
+    let kept_pages: HashSet<ObjectId> = page_ids.iter().copied().collect();
+    for &page_id in page_ids {
+        let Some(annots) = doc
+            .get_dictionary(page_id)
+            .ok()
+            .and_then(|page| page.get(b"Annots").ok())
+            .and_then(|annots| doc.dereference(annots).ok())
+            .and_then(|(_, annots)| annots.as_array().ok())
+            .cloned()
+        else {
+            continue;
+        };
+        let mut kept: Vec<Object> = annots
+            .into_iter()
+            .filter(|item| {
+                let Some(annot) = doc
+                    .dereference(item)
+                    .ok()
+                    .and_then(|(_, annot)| annot.as_dict().ok())
+                else {
+                    return false;
+                };
+                match link_target(doc, annot) {
+                    LinkTarget::None => true,
+                    LinkTarget::Page(target) => kept_pages.contains(&target),
+                    LinkTarget::Named => false,
+                }
+            })
+            .collect();
+        for item in &mut kept {
+            let annot = match item {
+                Object::Reference(id) => doc.get_object_mut(*id).and_then(Object::as_dict_mut).ok(),
+                Object::Dictionary(annot) => Some(annot),
+                _ => None,
+            };
+            if let Some(annot) = annot {
+                annot.remove(b"P");
+                annot.remove(b"Parent");
+            }
+        }
+        if let Ok(page) = doc.get_object_mut(page_id).and_then(Object::as_dict_mut) {
+            if kept.is_empty() {
+                page.remove(b"Annots");
+            } else {
+                page.set("Annots", kept);
+            }
+        }
+    }
+}
+
+fn extract_segment(source: &Document, segment: &Segment, title: &str) -> Result<Document, String> {
+    let structure_err = |e: lopdf::Error| format!("PDF 页面结构异常: {e}");
+    let mut doc = source.clone();
+    let pages = doc.get_pages();
+    let page_ids: Vec<ObjectId> = (segment.start..=segment.end)
+        .filter_map(|n| pages.get(&n).copied())
+        .collect();
+    for &page_id in &page_ids {
+        materialize_inherited(&mut doc, page_id);
+    }
+
+    let root_id = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(structure_err)?;
+    for &page_id in &page_ids {
+        if let Ok(page) = doc.get_object_mut(page_id).and_then(Object::as_dict_mut) {
+            page.set("Parent", root_id);
+        }
+    }
+    let root = doc
+        .get_object_mut(root_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(structure_err)?;
+    root.set(
+        "Kids",
+        page_ids
+            .iter()
+            .map(|&id| Object::Reference(id))
+            .collect::<Vec<_>>(),
+    );
+    root.set("Count", page_ids.len() as i64);
+    root.remove(b"Parent");
+
+    // 这些条目都指向原书的页面，拆出来的文件里没有意义，留着还会把段外页面一并保住
+    let catalog = doc.catalog_mut().map_err(structure_err)?;
+    for key in CATALOG_PAGE_REFS {
+        catalog.remove(key);
+    }
+    prune_annotations(&mut doc, &page_ids);
+    pdf_meta::set_info(
+        &mut doc,
+        &PdfMeta {
+            title: title.to_string(),
+            subject: String::new(),
+            keywords: String::new(),
+        },
+    )?;
+    doc.prune_objects();
+    Ok(doc)
+}
+
+fn output_dir(info: &PdfSplitInfo, source: &Path) -> Result<PathBuf, String> {
+    if let Some(dir) = info.output_dir.as_deref().filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let parent = source.parent().ok_or("无法确定拆分输出目录")?;
+    let stem = source
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(parent.join(format!("{}（拆分）", sanitize_name(&stem))))
+}
+
+fn split_blocking(info: &PdfSplitInfo) -> Result<Vec<PathBuf>, String> {
+    let source_path = Path::new(&info.file_path);
+    if !source_path.exists() {
+        return Err(format!("文件不存在: {}", info.file_path));
+    }
+    let source = Document::load(source_path).map_err(|e| format!("解析 PDF 失败: {e}"))?;
+    if source.is_encrypted() {
+        return Err("PDF 已加密，无法拆分".to_string());
+    }
+    let page_count = source.get_pages().len() as u32;
+    let segments = if info.ranges.is_empty() {
+        let segments = segments_from_toc(&outline_entries(&source), page_count);
+        if segments.is_empty() {
+            return Err("该 PDF 没有可用于拆分的书签，请按页码范围拆分".to_string());
+        }
+        segments
+    } else {
+        segments_from_ranges(&info.ranges, page_count)?
+    };
+
+    let book_title = source_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = output_dir(info, source_path)?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("创建输出目录失败: {e}"))?;
+
+    let mut outputs = Vec::with_capacity(segments.len());
+    for (i, segment) in segments.iter().enumerate() {
+        let mut doc = extract_segment(
+            &source,
+            segment,
+            &format!("{book_title} {}", segment.title),
+        )?;
+        let path = dir.join(format!(
+            "{:02} {}.pdf",
+            i + 1,
+            sanitize_name(&segment.title)
+        ));
+        doc.save(&path)
+            .map_err(|e| format!("保存 {} 失败: {e}", path.display()))?;
+        outputs.push(path);
+    }
+    log::info!(
+        "已将 {} 拆分为 {} 个文件: {}",
+        info.file_path,
+        outputs.len(),
+        dir.display()
+    );
+    Ok(outputs)
+}
+
+/// 拆分教材 PDF，返回各输出文件路径（按页码顺序）
+pub(super) async fn split_pdf(info: PdfSplitInfo) -> Result<Vec<PathBuf>, String> {
+    tokio::task::spawn_blocking(move || split_blocking(&info))
+        .await
+        .map_err(|e| format!("拆分任务异常: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{Stream, dictionary};
+
+    fn entry(level: usize, title: &str, page: u32) -> (usize, String, u32) {
+        (level, title.to_string(), page)
+    }
+
+    #[test]
+    fn segments_follow_the_first_level_with_siblings() {
+        let entries = vec![
+            entry(1, "语文 七年级上册", 1),
+            entry(2, "第一单元", 3),
+            entry(3, "1 春", 4),
+            entry(2, "第二单元", 20),
+            entry(2, "附录", 40),
+        ];
+        let segments = segments_from_toc(&entries, 45);
+        let spans: Vec<(u32, u32)> = segments.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(spans, vec![(3, 19), (20, 39), (40, 45)]);
+        assert_eq!(segments[0].title, "第一单元");
+
+        assert!(segments_from_toc(&[entry(1, "书名", 1)], 10).is_empty());
+        let ranges = vec![PdfSplitRange {
+            title: String::new(),
+            start_page: 2,
+            end_page: 5,
+        }];
+        assert_eq!(segments_from_ranges(&ranges, 10).unwrap()[0].title, "第2-5页");
+        assert!(segments_from_ranges(&ranges, 4).is_err());
+    }
+
+    #[test]
+    fn extracted_segment_keeps_only_its_pages() {
+        let source = pdf_meta::blank_pdf(5);
+        let segment = Segment {
+            title: "第一单元".to_string(),
+            start: 2,
+            end: 3,
+        };
+        let mut doc = extract_segment(&source, &segment, "语文 第一单元").unwrap();
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        let doc = Document::load_mem(&bytes).unwrap();
+        assert_eq!(doc.get_pages().len(), 2);
+        // 页面自带 MediaBox，继承属性补齐后也不应丢
+        let first = doc.get_pages()[&1];
+        assert!(doc.get_dictionary(first).unwrap().has(b"MediaBox"));
+        // 原文档不受影响
+        assert_eq!(source.get_pages().len(), 5);
+    }
+
+    // 命名目标、结构树、跳到段外的链接都去掉后，段外页面及其内容流不再留在输出里
+    #[test]
+    fn extracted_segment_drops_objects_of_other_pages() {
+        let mut source = pdf_meta::blank_pdf(5);
+        let pages = source.get_pages();
+        for (&number, &page_id) in &pages {
+            let content = source.add_object(Stream::new(
+                dictionary! {},
+                vec![b'%'; 4096 * number as usize],
+            ));
+            source
+                .get_object_mut(page_id)
+                .and_then(Object::as_dict_mut)
+                .unwrap()
+                .set("Contents", content);
+        }
+        let (second, third, fifth) = (pages[&2], pages[&3], pages[&5]);
+        let dest = |page: ObjectId| Object::Array(vec![page.into(), "Fit".into()]);
+        let outside = source.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Link", "P" => second, "Dest" => dest(fifth),
+        });
+        let inside = source.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Link", "P" => second,
+            "A" => dictionary! { "S" => "GoTo", "D" => dest(third) },
+        });
+        source
+            .get_object_mut(second)
+            .and_then(Object::as_dict_mut)
+            .unwrap()
+            .set("Annots", vec![outside.into(), inside.into()]);
+        let names = source.add_object(dictionary! {
+            "Dests" => dictionary! { "Names" => vec![Object::string_literal("end"), dest(fifth)] },
+        });
+        let tree = source.add_object(dictionary! {
+            "Type" => "StructTreeRoot",
+            "K" => dictionary! { "Pg" => pages[&4] },
+        });
+        let catalog = source.catalog_mut().unwrap();
+        catalog.set("Names", names);
+        catalog.set("StructTreeRoot", tree);
+        catalog.set("Dests", dictionary! { "last" => dest(fifth) });
+
+        let segment = Segment {
+            title: "第一单元".to_string(),
+            start: 2,
+            end: 3,
+        };
+        let mut doc = extract_segment(&source, &segment, "语文 第一单元").unwrap();
+        let page_objects = doc
+            .objects
+            .values()
+            .filter(|object| {
+                object
+                    .as_dict()
+                    .is_ok_and(|dict| dict.get(b"Type").and_then(Object::as_name).ok() == Some(b"Page"))
+            })
+            .count();
+        assert_eq!(page_objects, 2);
+        assert!(doc.objects.len() < source.objects.len() - 5);
+
+        let annots = doc
+            .get_dictionary(second)
+            .unwrap()
+            .get(b"Annots")
+            .and_then(Object::as_array)
+            .unwrap();
+        assert_eq!(annots, &vec![Object::Reference(inside)]);
+
+        let mut whole = Vec::new();
+        source.clone().save_to(&mut whole).unwrap();
+        let mut part = Vec::new();
+        doc.save_to(&mut part).unwrap();
+        assert!(part.len() * 2 < whole.len());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::api::books;
+use crate::api::{books, courses};
 use crate::http::CLIENT;
 use crate::models::TextbookDownloadInfo;
 use futures_util::StreamExt;
@@ -126,40 +126,80 @@ fn extract_file_extension(url: &Url) -> String {
         .unwrap_or_default()
 }
 
+// 教材的资源 id：请求里带的，旧版请求没有时从下载地址里解析
+pub(super) fn resource_id_of(info: &TextbookDownloadInfo) -> Option<&str> {
+    Some(info.resource_id.as_str())
+        .filter(|id| !id.is_empty())
+        .or_else(|| books::resource_id_from_url(&info.url))
+}
+
+// 教材的分类名称：请求里带的 labels → 旧版各级 label 字段 → 按资源 id 从书目还原
+async fn textbook_labels(info: &TextbookDownloadInfo) -> Vec<String> {
+    if !info.labels.is_empty() {
+        return info.labels.clone();
+    }
+    let legacy: Vec<String> = [
+        &info.category_label,
+        &info.subject_label,
+        &info.version_label,
+        &info.grade_label,
+        &info.year_label,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|label| !label.is_empty())
+    .cloned()
+    .collect();
+    if !legacy.is_empty() {
+        return legacy;
+    }
+    match resource_id_of(info) {
+        Some(id) => books::resolve_book_labels(id).await.unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
 // 「按分类保存」时用各级标签名拼出子目录（标签名清洗，防止特殊字符拼出意外层级）
-fn build_save_path(info: &TextbookDownloadInfo, download_path: &str) -> PathBuf {
+async fn build_save_path(info: &TextbookDownloadInfo, download_path: &str) -> PathBuf {
     let mut path = PathBuf::from(download_path);
-
-    if info.save_by_category {
-        let labels = [
-            &info.category_label,
-            &info.subject_label,
-            &info.version_label,
-            &info.grade_label,
-            &info.year_label,
-        ];
-        for label in labels.iter().filter_map(|l| l.as_ref()) {
-            if !label.is_empty() {
-                path.push(sanitize_name(label));
-            }
-        }
+    if !info.save_by_category {
+        return path;
     }
 
+    // 列表里算好的目录优先，与界面展示的分类一致；逐段再清洗一遍，防止拼出上级目录
+    let relative = if info.save_path.is_empty() {
+        books::category_save_path(&textbook_labels(info).await)
+    } else {
+        let segments: Vec<String> = info.save_path.split('/').map(str::to_string).collect();
+        books::category_save_path(&segments)
+    };
+    if !relative.is_empty() {
+        path.push(relative);
+    }
     path
 }
 
 fn create_request(url: &Url, token: Option<&str>) -> reqwest::RequestBuilder {
-    let mut request = CLIENT
+    let request = CLIENT
         .get(url.clone())
         .header(reqwest::header::USER_AGENT, USER_AGENT);
+    with_auth(request, url, token)
+}
 
-    if let Some(t) = token {
-        // 私有桶用 ND UC 的 MAC 鉴权头，服务端只校验 token id，
-        // nonce/mac 可为占位值（Bearer 会被 400 拒绝）
-        request = request.header("x-nd-auth", format!("MAC id=\"{t}\",nonce=\"0\",mac=\"0\""));
+// 私有桶用 ND UC 的 MAC 鉴权头，服务端只校验 token id，
+// nonce/mac 可为占位值（Bearer 会被 400 拒绝）。
+// 只发给平台域名：直链、m3u8 里的切片/KEY 地址可能指向别的主机，不能把登录凭据带过去
+pub(super) fn with_auth(
+    request: reqwest::RequestBuilder,
+    url: &Url,
+    token: Option<&str>,
+) -> reqwest::RequestBuilder {
+    match token {
+        Some(t) if url.host_str().is_some_and(courses::is_cdn_host) => {
+            request.header("x-nd-auth", format!("MAC id=\"{t}\",nonce=\"0\",mac=\"0\""))
+        }
+        _ => request,
     }
-
-    request
 }
 
 fn calculate_progress(downloaded: u64, total: Option<u64>) -> u32 {
@@ -183,6 +223,8 @@ struct ResumableResponse {
     resume_from: u64,
     // 最终文件总大小（续传时 = 剩余长度 + 起点）
     total_size: Option<u64>,
+    // host 连接名额，随响应一起持有到流写完
+    _host_slot: tokio::sync::OwnedSemaphorePermit,
 }
 
 // 发起下载请求；part_path 已有半成品时带 Range 续传。
@@ -194,6 +236,8 @@ async fn open_resumable(
     part_path: &Path,
 ) -> Result<ResumableResponse, String> {
     let mut resume_from = fs::metadata(part_path).await.map(|m| m.len()).unwrap_or(0);
+    // 与切片下载共用按 host 的连接预算
+    let host_slot = super::throttle::acquire_host_slot(url.as_str()).await;
 
     loop {
         let mut request = create_request(url, token);
@@ -223,6 +267,7 @@ async fn open_resumable(
             response,
             resume_from,
             total_size,
+            _host_slot: host_slot,
         });
     }
 }
@@ -239,6 +284,7 @@ async fn stream_to_part_file(
         response,
         resume_from,
         total_size,
+        _host_slot,
     } = opened;
     let mut stream = response.bytes_stream();
 
@@ -316,10 +362,13 @@ async fn download_resumable(
 }
 
 // 候选下载地址：详情声明的源 PDF 优先（部分教材的 pkg 没有 pdf.pdf 别名），
-// 传入的构造 URL 兜底
-async fn download_candidates(url: &str) -> Vec<String> {
+// 传入的构造 URL 兜底。资源 id 优先用前端记下的，旧任务从 URL 里提取
+async fn download_candidates(url: &str, resource_id: &str) -> Vec<String> {
     let mut candidates = Vec::new();
-    if let Some(id) = books::resource_id_from_url(url) {
+    let id = Some(resource_id)
+        .filter(|id| !id.is_empty())
+        .or_else(|| books::resource_id_from_url(url));
+    if let Some(id) = id {
         candidates = books::resolve_source_pdf_urls(id).await;
     }
     if !candidates.iter().any(|c| c == url) {
@@ -348,7 +397,7 @@ pub(super) async fn run(
     let emitter = DownloadEventEmitter::new(app_handle, url.clone());
     emitter.emit_status(DownloadStatus::Downloading, 0);
 
-    let base_save_path = build_save_path(&textbook_info, &download_path);
+    let base_save_path = build_save_path(&textbook_info, &download_path).await;
     if !base_save_path.exists() {
         fs::create_dir_all(&base_save_path)
             .await
@@ -358,8 +407,8 @@ pub(super) async fn run(
     // 依次尝试候选地址：初始请求失败换下一个；请求成功后按其扩展名确定目标文件，
     // 已有 .part 半成品时自动续传
     let mut last_error = "下载失败".to_string();
-    let mut completed: Option<PathBuf> = None;
-    for candidate in download_candidates(url).await {
+    let mut completed: Option<(PathBuf, String)> = None;
+    for candidate in download_candidates(url, &textbook_info.resource_id).await {
         if cancellation_token.is_cancelled() {
             return Err("下载已取消".to_string());
         }
@@ -407,16 +456,29 @@ pub(super) async fn run(
             return Err(e);
         }
 
-        completed = Some(save_path);
+        completed = Some((save_path, candidate));
         break;
     }
 
-    let Some(save_path) = completed else {
+    let Some((save_path, downloaded_from)) = completed else {
         emitter.emit_status(DownloadStatus::Failed(last_error.clone()), 0);
         return Err(last_error);
     };
 
     log::info!("下载完成: {}", save_path.display());
+    if save_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")) {
+        let resource_id = resource_id_of(&textbook_info).unwrap_or_default();
+        let meta = super::pdf_meta::PdfMeta {
+            title: textbook_info.title.clone(),
+            subject: textbook_labels(&textbook_info).await.join(" / "),
+            keywords: resource_id.to_string(),
+        };
+        // 元数据只是锦上添花，写入失败不影响下载结果
+        if let Err(e) = super::pdf_meta::write(&save_path, meta).await {
+            log::warn!("写入 PDF 元数据失败: {e}");
+        }
+    }
+    super::library::record(&textbook_info, &downloaded_from, &save_path).await;
 
     let file_path_str = save_path.to_string_lossy().into_owned();
     emitter.emit_completed(&file_path_str);
@@ -425,7 +487,7 @@ pub(super) async fn run(
 }
 
 // 文件名/目录名清洗：去掉路径非法字符，避免拼接出非法路径
-fn sanitize_name(name: &str) -> String {
+pub(crate) fn sanitize_name(name: &str) -> String {
     let cleaned: String = name
         .chars()
         .map(|c| match c {
@@ -441,8 +503,148 @@ fn sanitize_name(name: &str) -> String {
     }
 }
 
+// 视频时间段：起止都未给时为 None（整段下载）
+pub(super) fn course_time_range(
+    resource: &crate::models::CourseDownloadInfo,
+) -> Result<Option<super::m3u8::TimeRange>, String> {
+    if !resource.is_video || (resource.start_seconds.is_none() && resource.end_seconds.is_none()) {
+        return Ok(None);
+    }
+    let start = resource.start_seconds.unwrap_or(0.0);
+    let valid = start.is_finite()
+        && start >= 0.0
+        && resource.end_seconds.is_none_or(|end| end.is_finite() && end > start);
+    if !valid {
+        return Err("时间段无效：结束时间须晚于开始时间".to_string());
+    }
+    Ok(Some(super::m3u8::TimeRange {
+        start,
+        end: resource.end_seconds,
+        precise: resource.precise_trim,
+    }))
+}
+
+// 课程资源在前端任务池里的键，进度事件与取消令牌都以它为准
+pub(super) fn course_task_key(resource: &crate::models::CourseDownloadInfo) -> &str {
+    if resource.task_key.is_empty() {
+        &resource.download_url
+    } else {
+        &resource.task_key
+    }
+}
+
+// 课程资源的目标路径。「按分类保存」时先按分类目录段分层，同一课程的多个资源
+// 再归到课程标题子目录；时间段下载在文件名后附上区间，与整段下载互不覆盖。
+// 预览与正式下载共用，保证切片缓存目录一致
+pub(super) fn course_save_path(
+    resource: &crate::models::CourseDownloadInfo,
+    download_path: &str,
+) -> PathBuf {
+    let mut base_save_path = PathBuf::from(download_path);
+    if resource.save_by_category {
+        for seg in resource.category_path.iter().filter(|s| !s.trim().is_empty()) {
+            base_save_path.push(sanitize_name(seg));
+        }
+    }
+    if let Some(course) = resource.course_title.as_deref().filter(|s| !s.is_empty()) {
+        base_save_path.push(sanitize_name(course));
+    }
+
+    let mut title = sanitize_name(&resource.title);
+    if let Ok(Some(range)) = course_time_range(resource) {
+        title.push_str(&format!("（{}）", range.label()));
+    }
+    let ext = if resource.format.is_empty() {
+        "bin"
+    } else {
+        resource.format.as_str()
+    };
+    base_save_path.join(format!("{title}.{ext}"))
+}
+
+// 资源身份（详情接口地址 + 资源 id + ti_file_flag）齐全时才能重新解析地址
+fn has_resource_identity(resource: &crate::models::CourseDownloadInfo) -> bool {
+    !resource.detail_url.is_empty()
+        && !resource.resource_id.is_empty()
+        && !resource.ti_file_flag.is_empty()
+}
+
+// 目标路径旁已有半成品（.part 文件或 .parts 切片目录），即这次是续传
+fn has_partial_download(save_path: &Path) -> bool {
+    path_with_suffix(save_path, ".part").exists() || path_with_suffix(save_path, ".parts").exists()
+}
+
+// 从详情接口取最新地址（主地址 + 各镜像）：续传时签名地址可能已过期。
+// 取不到返回 None，由调用方沿用任务记下的原地址。
+// 目标路径只由标题等决定，换地址后仍续传原有半成品
+async fn fresh_download_urls(
+    resource: &crate::models::CourseDownloadInfo,
+) -> Option<(String, Vec<String>)> {
+    match crate::api::courses::refresh_resource_urls(
+        &resource.detail_url,
+        &resource.resource_id,
+        &resource.ti_file_flag,
+    )
+    .await
+    {
+        Ok(urls) => {
+            let url = urls[0].clone();
+            if url != resource.download_url {
+                log::info!("资源地址已更新: {url}");
+            }
+            Some((url, urls))
+        }
+        Err(e) => {
+            log::warn!("重新解析资源地址失败，沿用原地址: {e}");
+            None
+        }
+    }
+}
+
+// 按给定地址下载课程资源到 save_path，返回实际写入的路径与可选告警
+async fn fetch_course_resource(
+    resource: &crate::models::CourseDownloadInfo,
+    (fetch_url, mirror_urls): &(String, Vec<String>),
+    token: Option<&str>,
+    save_path: &Path,
+    ffmpeg_path: Option<&str>,
+    cancellation_token: &CancellationToken,
+    emitter: &DownloadEventEmitter,
+) -> Result<(PathBuf, Option<String>), String> {
+    if resource.is_video {
+        // 视频合成后的真实路径可能是 .mp4（ffmpeg 转封装成功）或 .ts（回退）
+        let output = super::m3u8::VideoOutput {
+            ffmpeg_path: ffmpeg_path.map(str::to_string),
+            metadata: super::m3u8::VideoMetadata {
+                title: resource.title.clone(),
+                course_title: resource.course_title.clone(),
+                category_path: resource.category_path.clone(),
+                source_id: resource.resource_id.clone(),
+                source_url: resource.source_url.clone(),
+                cover_url: resource.cover_url.clone(),
+            },
+            range: course_time_range(resource)?,
+        };
+        super::m3u8::download(
+            fetch_url,
+            mirror_urls,
+            token,
+            save_path,
+            &output,
+            cancellation_token,
+            emitter,
+        )
+        .await
+    } else {
+        let parsed = Url::parse(fetch_url).map_err(|e| format!("无效的 URL: {e}"))?;
+        download_resumable(&parsed, token, save_path, cancellation_token, emitter)
+            .await
+            .map(|_| (save_path.to_path_buf(), None))
+    }
+}
+
 /// 下载单个课程资源：视频走 m3u8 解密流程（按切片续传），其余走普通流式下载（Range 续传）。
-/// 事件以 resource.download_url 为键，与前端下载状态仓库对应。
+/// 事件以 course_task_key 为键，与前端下载状态仓库对应。
 pub(super) async fn run_course(
     app_handle: tauri::AppHandle,
     resource: crate::models::CourseDownloadInfo,
@@ -451,7 +653,7 @@ pub(super) async fn run_course(
     ffmpeg_path: Option<String>,
     cancellation_token: CancellationToken,
 ) -> Result<String, String> {
-    let url = resource.download_url.clone();
+    let url = course_task_key(&resource).to_string();
     log::info!("开始下载课程资源《{}》: {url}", resource.title);
 
     if cancellation_token.is_cancelled() {
@@ -464,54 +666,51 @@ pub(super) async fn run_course(
     let emitter = DownloadEventEmitter::new(app_handle, url.clone());
     emitter.emit_status(DownloadStatus::Downloading, 0);
 
-    // 「按分类保存」时先按分类目录段分层，同一课程的多个资源再归到课程标题子目录
-    let mut base_save_path = PathBuf::from(&download_path);
-    if resource.save_by_category {
-        for seg in resource.category_path.iter().filter(|s| !s.trim().is_empty()) {
-            base_save_path.push(sanitize_name(seg));
-        }
-    }
-    if let Some(course) = resource.course_title.as_deref().filter(|s| !s.is_empty()) {
-        base_save_path.push(sanitize_name(course));
-    }
-    if !base_save_path.exists() {
-        fs::create_dir_all(&base_save_path)
+    // 先校验时间段：course_save_path 遇到无效区间时不附区间标签
+    course_time_range(&resource)?;
+    let save_path = course_save_path(&resource, &download_path);
+    if let Some(base_save_path) = save_path.parent().filter(|p| !p.exists()) {
+        fs::create_dir_all(base_save_path)
             .await
             .map_err(|e| format!("创建下载目录失败: {e}"))?;
     }
-
-    let title = sanitize_name(&resource.title);
-    let ext = if resource.format.is_empty() {
-        "bin".to_string()
-    } else {
-        resource.format.clone()
-    };
-    let save_path = base_save_path.join(format!("{title}.{ext}"));
     emitter.emit_target_path(&save_path);
 
-    let download_result: Result<(PathBuf, Option<String>), String> = if resource.is_video {
-        // 视频合成后的真实路径可能是 .mp4（ffmpeg 转封装成功）或 .ts（回退）
-        super::m3u8::download(
-            &url,
-            token.as_deref(),
-            &save_path,
-            ffmpeg_path.as_deref(),
-            &cancellation_token,
-            &emitter,
-        )
-        .await
+    // 首次下载直接用解析时拿到的地址；续传（签名可能已过期）或原地址失败时
+    // 再按资源身份从详情接口取最新地址
+    let recorded = (resource.download_url.clone(), resource.mirror_urls.clone());
+    let identified = has_resource_identity(&resource);
+    let resuming = identified && has_partial_download(&save_path);
+    let urls = if resuming {
+        fresh_download_urls(&resource).await.unwrap_or_else(|| recorded.clone())
     } else {
-        let parsed = Url::parse(&url).map_err(|e| format!("无效的 URL: {e}"))?;
-        download_resumable(
-            &parsed,
-            token.as_deref(),
-            &save_path,
-            &cancellation_token,
-            &emitter,
-        )
-        .await
-        .map(|_| (save_path, None))
+        recorded.clone()
     };
+    let mut download_result = fetch_course_resource(
+        &resource,
+        &urls,
+        token.as_deref(),
+        &save_path,
+        ffmpeg_path.as_deref(),
+        &cancellation_token,
+        &emitter,
+    )
+    .await;
+    if download_result.is_err() && identified && !resuming && !cancellation_token.is_cancelled() {
+        if let Some(fresh) = fresh_download_urls(&resource).await.filter(|f| *f != recorded) {
+            log::info!("原地址下载失败，改用最新地址重试");
+            download_result = fetch_course_resource(
+                &resource,
+                &fresh,
+                token.as_deref(),
+                &save_path,
+                ffmpeg_path.as_deref(),
+                &cancellation_token,
+                &emitter,
+            )
+            .await;
+        }
+    }
 
     let (final_path, warning) = download_result.inspect_err(|e| {
         // 取消不算失败，cancelled 事件由命令包装层统一补发
@@ -0,0 +1,186 @@
+// 下载连接数控制，两层：
+// - 进程级按 host 的连接预算：多个视频 / 文件并行下载共用同一份额度，
+//   避免「3 个视频 × 8 路切片」叠加出几十路连接触发 CDN 限流
+// - 单个视频的自适应切片并发（AIMD）：连续成功逐步加一路，遇到错误 / 429 / 5xx 减半
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+// 每个 host 同时打开的下载连接上限（切片请求与直链流式下载共用）
+const HOST_CONNECTION_BUDGET: usize = 12;
+
+// 单个视频的切片并发：起步保守，按吞吐逐步放开，出错后减半但不低于下限
+pub(super) const SEGMENT_CONCURRENCY_INITIAL: usize = 4;
+pub(super) const SEGMENT_CONCURRENCY_MIN: usize = 1;
+pub(super) const SEGMENT_CONCURRENCY_MAX: usize = 16;
+
+static HOST_BUDGETS: Lazy<Mutex<HashMap<String, Arc<Semaphore>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn host_of(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+// 占用目标 host 的一个连接名额，permit 释放即归还。直链下载要持有到整个流写完
+pub(super) async fn acquire_host_slot(url: &str) -> OwnedSemaphorePermit {
+    let semaphore = {
+        let mut budgets = HOST_BUDGETS.lock().unwrap();
+        Arc::clone(
+            budgets
+                .entry(host_of(url))
+                .or_insert_with(|| Arc::new(Semaphore::new(HOST_CONNECTION_BUDGET))),
+        )
+    };
+    semaphore
+        .acquire_owned()
+        .await
+        .expect("host 连接预算信号量不会被关闭")
+}
+
+// 限流 / 服务端过载：这类错误说明并发开大了，要退让
+pub(super) fn is_throttle_error(message: &str) -> bool {
+    message.contains("HTTP 429") || message.contains("HTTP 5")
+}
+
+struct LimiterState {
+    // 当前目标并发
+    limit: usize,
+    // 信号量里实际流通的 permit 总数；减并发时多出的 permit 在归还时回收
+    issued: usize,
+    // 自上次调整以来的连续成功数
+    streak: usize,
+}
+
+pub(super) struct AdaptiveLimiter {
+    semaphore: Semaphore,
+    state: Mutex<LimiterState>,
+}
+
+impl AdaptiveLimiter {
+    pub(super) fn new() -> Self {
+        Self {
+            semaphore: Semaphore::new(SEGMENT_CONCURRENCY_INITIAL),
+            state: Mutex::new(LimiterState {
+                limit: SEGMENT_CONCURRENCY_INITIAL,
+                issued: SEGMENT_CONCURRENCY_INITIAL,
+                streak: 0,
+            }),
+        }
+    }
+
+    pub(super) async fn acquire(&self) -> AdaptivePermit<'_> {
+        let permit = self
+            .semaphore
+            .acquire()
+            .await
+            .expect("切片并发信号量不会被关闭");
+        AdaptivePermit {
+            limiter: self,
+            permit: Some(permit),
+        }
+    }
+
+    // 连续成功一整轮（次数等于当前并发）视为吞吐稳定，加一路
+    pub(super) fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.streak += 1;
+        if state.streak < state.limit || state.limit >= SEGMENT_CONCURRENCY_MAX {
+            return;
+        }
+        state.streak = 0;
+        state.limit += 1;
+        if state.issued < state.limit {
+            state.issued += 1;
+            self.semaphore.add_permits(1);
+        }
+    }
+
+    // 出错减半；多余的 permit 不强行收回，等在途请求归还时丢弃
+    pub(super) fn on_error(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.streak = 0;
+        let reduced = (state.limit / 2).max(SEGMENT_CONCURRENCY_MIN);
+        if reduced < state.limit {
+            log::info!("切片并发下调: {} → {reduced}", state.limit);
+            state.limit = reduced;
+        }
+    }
+
+    pub(super) fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+}
+
+pub(super) struct AdaptivePermit<'a> {
+    limiter: &'a AdaptiveLimiter,
+    permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for AdaptivePermit<'_> {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+        let mut state = self.limiter.state.lock().unwrap();
+        if state.issued > state.limit {
+            state.issued -= 1;
+            permit.forget();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_after_a_full_round_of_successes() {
+        let limiter = AdaptiveLimiter::new();
+        for _ in 0..SEGMENT_CONCURRENCY_INITIAL {
+            limiter.on_success();
+        }
+        assert_eq!(limiter.limit(), SEGMENT_CONCURRENCY_INITIAL + 1);
+        assert_eq!(limiter.semaphore.available_permits(), SEGMENT_CONCURRENCY_INITIAL + 1);
+    }
+
+    #[test]
+    fn never_exceeds_max_or_drops_below_min() {
+        let limiter = AdaptiveLimiter::new();
+        for _ in 0..1000 {
+            limiter.on_success();
+        }
+        assert_eq!(limiter.limit(), SEGMENT_CONCURRENCY_MAX);
+        for _ in 0..10 {
+            limiter.on_error();
+        }
+        assert_eq!(limiter.limit(), SEGMENT_CONCURRENCY_MIN);
+    }
+
+    // 减并发后，在途请求归还的多余 permit 要被回收，而不是回到池里
+    #[tokio::test]
+    async fn surplus_permits_are_retired_on_release() {
+        let limiter = AdaptiveLimiter::new();
+        let held: Vec<_> = futures_util::future::join_all(
+            (0..SEGMENT_CONCURRENCY_INITIAL).map(|_| limiter.acquire()),
+        )
+        .await;
+        limiter.on_error();
+        drop(held);
+        assert_eq!(
+            limiter.semaphore.available_permits(),
+            SEGMENT_CONCURRENCY_INITIAL / 2
+        );
+    }
+
+    #[test]
+    fn classifies_throttle_errors() {
+        assert!(is_throttle_error("HTTP 429 Too Many Requests"));
+        assert!(is_throttle_error("HTTP 503 Service Unavailable"));
+        assert!(!is_throttle_error("HTTP 404 Not Found"));
+    }
+}
@@ -1,5 +1,6 @@
 use once_cell::sync::Lazy;
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
 // 全局共享客户端：复用连接池；只设连接超时，下载大文件走流式不设总超时
@@ -32,6 +33,67 @@ pub async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, String> {
         .map_err(|e| format!("解析 JSON 失败 {url}: {e}"))
 }
 
+// 条件请求的校验值：上次响应的 ETag / Last-Modified
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+}
+
+pub enum Conditional<T> {
+    // 304：内容未变
+    NotModified,
+    Modified(T, Validators),
+}
+
+fn header_value(response: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)?
+        .to_str()
+        .ok()
+        .map(str::to_string)
+}
+
+/// 带 If-None-Match / If-Modified-Since 的 GET；服务端返回 304 时不下载正文
+pub async fn get_json_conditional<T: DeserializeOwned>(
+    url: &str,
+    validators: &Validators,
+) -> Result<Conditional<T>, String> {
+    use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+
+    let mut request = CLIENT.get(url);
+    if let Some(etag) = &validators.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("请求失败 {url}: {e}"))?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(Conditional::NotModified);
+    }
+    if !status.is_success() {
+        return Err(format!("请求失败 {url}: HTTP {status}"));
+    }
+    let validators = Validators {
+        etag: header_value(&response, ETAG),
+        last_modified: header_value(&response, LAST_MODIFIED),
+    };
+    let value = response
+        .json()
+        .await
+        .map_err(|e| format!("解析 JSON 失败 {url}: {e}"))?;
+    Ok(Conditional::Modified(value, validators))
+}
+
 pub async fn get_bytes(url: &str) -> Result<bytes::Bytes, String> {
     get_checked(url)
         .await?
@@ -3,6 +3,7 @@ pub mod downloader;
 pub mod http;
 pub mod login;
 pub mod models;
+pub mod storage;
 pub mod system;
 pub mod updater;
 
@@ -12,10 +13,12 @@ const MIN_WINDOW_WIDTH: f64 = 800.0;
 const MIN_WINDOW_HEIGHT: f64 = 600.0;
 
 fn setup_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+    use tauri::Manager;
+
+    storage::init(app.path().app_data_dir()?);
+
     #[cfg(desktop)]
     {
-        use tauri::Manager;
-
         // updater / process 仅桌面端可用，在 setup 中按平台注册
         app.handle()
             .plugin(tauri_plugin_updater::Builder::new().build())?;
@@ -74,6 +77,9 @@ pub fn run() {
     let result = tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .register_asynchronous_uri_scheme_protocol(api::images::SCHEME, |_ctx, request, responder| {
+            api::images::handle(request, responder)
+        })
         .setup(setup_app)
         .on_menu_event(|_window, event| handle_menu_event(event.id.as_ref()))
         .invoke_handler(tauri::generate_handler![
@@ -81,13 +87,24 @@ pub fn run() {
             downloader::cancel_download,
             downloader::download_course_resource,
             downloader::remove_download_artifacts,
+            downloader::check_outdated_textbooks,
             downloader::check_ffmpeg,
+            downloader::merge_course_videos,
+            downloader::split_textbook_pdf,
+            downloader::start_video_preview,
+            downloader::stop_video_preview,
             api::fetch_textbooks,
             api::fetch_filter_options,
+            api::fetch_tag_children,
+            api::fetch_textbooks_by_path,
             api::fetch_textbook_categories,
-            api::fetch_cover,
-            api::fetch_image,
+            api::fetch_catalog_changes,
+            api::search::search_textbooks,
+            api::export::export_textbooks,
             api::courses::parse_course_url,
+            api::lessons::fetch_lesson_tree,
+            api::lessons::parse_lesson_unit,
+            api::companions::fetch_companion_resources,
             api::clear_tch_material_tag_cache,
             login::open_login_window,
             system::open_download_folder_prompt,
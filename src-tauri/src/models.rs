@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextbookDownloadInfo {
     pub url: String,
     pub title: String,
@@ -11,9 +11,19 @@ pub struct TextbookDownloadInfo {
     pub grade_label: Option<String>,
     pub year_label: Option<String>,
     pub save_by_category: bool,
+    // 分类名称链（Textbook.labels）；为空时退回上面的各级 label，
+    // 都没有则按 resource_id 从书目里还原
+    #[serde(default)]
+    pub labels: Vec<String>,
+    // 资源 id：续传时据此重新解析源 PDF 地址（为空时从 url 里提取）
+    #[serde(default)]
+    pub resource_id: String,
+    // 「按分类保存」的相对目录（Textbook.save_path）；为空时按上面的名称链拼
+    #[serde(default)]
+    pub save_path: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Textbook {
     pub id: String,
     pub cover_url: String,
@@ -21,6 +31,17 @@ pub struct Textbook {
     pub total_uv: i64,
     pub like_count: i64,
     pub download_url: String,
+    // 分类路径上的名称（如 小学 / 语文 / 统编版 / 一年级），由 tag_paths 还原
+    pub labels: Vec<String>,
+    // 「按分类保存」时的相对目录（各级名称清洗后以 / 连接）
+    pub save_path: String,
+}
+
+// 教材列表；offline 表示网络不可用，结果来自本地缓存的旧目录
+#[derive(Debug, Clone, Serialize)]
+pub struct TextbookList {
+    pub textbooks: Vec<Textbook>,
+    pub offline: bool,
 }
 
 // 一个课程 URL 解析后得到的资源（可能是视频，也可能是课件文档）。
@@ -36,12 +57,20 @@ pub struct CourseResource {
     // true 表示需要 m3u8 解密下载流程，false 表示直接流式下载
     pub is_video: bool,
     pub cover_url: String,
+    // 下载地址取自哪个 ti_item（ti_file_flag，如 href-720p-m3u8）；直链为空
+    pub ti_file_flag: String,
+    // 同一文件在各 CDN 镜像（r1/r2/r3）上的地址，含 download_url；视频切片分散到各镜像下载
+    pub mirror_urls: Vec<String>,
 }
 
 // 一个课程解析结果：课程标题 + 分类目录段（学段/学科/…，可为空）+ 其下的资源清单
 #[derive(Debug, Clone, Serialize)]
 pub struct CourseParseResult {
     pub title: String,
+    // 解析所用的平台页面地址，写入视频元数据便于追溯来源
+    pub source_url: String,
+    // 平台详情接口地址；签名地址过期后据此重新解析资源。直链为空
+    pub detail_url: String,
     pub category_path: Vec<String>,
     pub resources: Vec<CourseResource>,
 }
@@ -60,10 +89,73 @@ pub struct CourseDownloadInfo {
     pub save_by_category: bool,
     #[serde(default)]
     pub category_path: Vec<String>,
+    // 以下写入视频 MP4 元数据：资源 id、平台页面地址、封面（嵌入为封面图）
+    #[serde(default)]
+    pub resource_id: String,
+    #[serde(default)]
+    pub source_url: String,
+    #[serde(default)]
+    pub cover_url: String,
+    // 资源身份：详情接口地址 + 选中的 ti_file_flag（配合 resource_id）。
+    // 平台签名地址会过期，续传时据此重新解析出新地址，继续写入原有半成品
+    #[serde(default)]
+    pub detail_url: String,
+    #[serde(default)]
+    pub ti_file_flag: String,
+    #[serde(default)]
+    pub mirror_urls: Vec<String>,
+    // 视频时间段下载（秒）：只下与该区间重叠的切片；都为空时下载整段。
+    // precise_trim 时用 ffmpeg 重新编码，精确裁到起止时间
+    #[serde(default)]
+    pub start_seconds: Option<f64>,
+    #[serde(default)]
+    pub end_seconds: Option<f64>,
+    #[serde(default)]
+    pub precise_trim: bool,
+    // 前端任务键（进度事件与取消令牌都用它）：片段下载为带区间的键，与整段下载互不冲突；
+    // 为空时即 download_url
+    #[serde(default)]
+    pub task_key: String,
+}
+
+// 合并课程视频的一个来源：已下载的文件及其资源标题（作为章节名）
+#[derive(Debug, Clone, Deserialize)]
+pub struct CourseMergePart {
+    pub file_path: String,
+    pub title: String,
+}
+
+// 合并课程视频：parts 按解析顺序排列；output_path 为空时放在第一个文件旁边
+#[derive(Debug, Clone, Deserialize)]
+pub struct CourseMergeInfo {
+    pub course_title: String,
+    pub parts: Vec<CourseMergePart>,
+    #[serde(default)]
+    pub output_path: Option<String>,
+}
+
+// 拆分教材 PDF 的一段：页码从 1 起、首尾都含；title 作为输出文件名（为空时按页码命名）
+#[derive(Debug, Clone, Deserialize)]
+pub struct PdfSplitRange {
+    #[serde(default)]
+    pub title: String,
+    pub start_page: u32,
+    pub end_page: u32,
+}
+
+// 拆分教材 PDF：ranges 为空时按 PDF 已有书签拆分；原文件保留，
+// 输出放在原文件旁的「<书名>（拆分）」目录（output_dir 可覆盖）
+#[derive(Debug, Clone, Deserialize)]
+pub struct PdfSplitInfo {
+    pub file_path: String,
+    #[serde(default)]
+    pub ranges: Vec<PdfSplitRange>,
+    #[serde(default)]
+    pub output_dir: Option<String>,
 }
 
 // 只保留实际用到的字段，几千条书目反序列化后能省不少内存
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct CustomProperties {
     #[serde(default)]
     pub preview: Option<Value>,
@@ -71,7 +163,7 @@ pub struct CustomProperties {
     pub thumbnails: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RawBook {
     #[serde(default)]
     pub id: String,
@@ -82,6 +174,36 @@ pub struct RawBook {
     pub custom_properties: Option<CustomProperties>,
 }
 
+// 通用标签导航的一个节点：is_leaf 表示其下没有更细的分类，可直接列书目
+#[derive(Debug, Clone, Serialize)]
+pub struct TagNode {
+    pub value: String,
+    pub label: String,
+    // 该节点下的书目数（书目不可用时为 None）
+    pub count: Option<usize>,
+    pub is_leaf: bool,
+}
+
+// fetch_tag_children 的返回：路径上各级名称、下一级节点、路径本身是否已到叶子
+#[derive(Debug, Clone, Serialize)]
+pub struct TagChildren {
+    pub labels: Vec<String>,
+    pub children: Vec<TagNode>,
+    pub is_leaf: bool,
+    // 标签树或计数用的书目来自本地缓存（网络不可用），可能不是最新
+    pub offline: bool,
+}
+
+impl From<TagNode> for DropdownOption {
+    fn from(node: TagNode) -> Self {
+        DropdownOption {
+            value: node.value,
+            label: node.label,
+            count: node.count,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct FilterOptionsArgs {
     pub category_id: Option<String>,
@@ -94,16 +216,25 @@ pub struct FilterOptionsArgs {
 pub struct DropdownOption {
     pub value: String,
     pub label: String,
+    // 该选项下的书目数（书目不可用时为 None）
+    pub count: Option<usize>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+// 下拉选项列表；offline 含义同 TextbookList
+#[derive(Debug, Clone, Serialize)]
+pub struct DropdownOptions {
+    pub options: Vec<DropdownOption>,
+    pub offline: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TagChild {
     pub tag_id: String,
     pub tag_name: String,
     pub hierarchies: Option<Vec<TagHierarchy>>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TagHierarchy {
     pub children: Option<Vec<TagChild>>,
 }
@@ -0,0 +1,174 @@
+// 应用数据目录下的持久化缓存（书目、标签树等），重启或断网时可直接复用。
+// 目录在 setup 阶段由 init 设定；未初始化（如单元测试）时读写一律跳过。
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs;
+
+static CACHE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+pub fn init(app_data_dir: PathBuf) {
+    let _ = CACHE_DIR.set(app_data_dir.join("cache"));
+}
+
+pub fn cache_path(name: &str) -> Option<PathBuf> {
+    CACHE_DIR.get().map(|dir| dir.join(name))
+}
+
+/// 读取缓存文件；不存在、损坏或结构已变（旧版本写入）都视为没有缓存
+pub async fn read_json<T: DeserializeOwned>(name: &str) -> Option<T> {
+    read_json_at(&cache_path(name)?).await
+}
+
+/// 写入缓存文件（临时文件 + 改名，中途退出不会留下半个文件）
+pub async fn write_json<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    let Some(path) = cache_path(name) else {
+        return Ok(());
+    };
+    write_json_at(&path, value).await
+}
+
+pub async fn remove(name: &str) {
+    if let Some(path) = cache_path(name) {
+        let _ = fs::remove_file(path).await;
+    }
+}
+
+/// 刷新文件修改时间，供按修改时间淘汰的缓存目录标记「最近用过」（阻塞调用）
+pub fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// 缓存目录超出条目数或总字节数上限时，按修改时间从旧到新删除，直到两者都满足（阻塞调用）
+pub fn prune_dir(dir: &Path, max_files: usize, max_bytes: u64) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = read_dir
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    let mut count = files.len();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if count <= max_files && total <= max_bytes {
+        return;
+    }
+    files.sort();
+    for (_, len, path) in files {
+        if count <= max_files && total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            count -= 1;
+            total = total.saturating_sub(len);
+        }
+    }
+}
+
+async fn read_json_at<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let bytes = fs::read(path).await.ok()?;
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::warn!("缓存文件无法解析，忽略 {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+async fn write_json_at<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建缓存目录失败: {e}"))?;
+    }
+    let json = serde_json::to_vec(value).map_err(|e| format!("序列化缓存失败: {e}"))?;
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    fs::write(&tmp_path, json)
+        .await
+        .map_err(|e| format!("写入缓存失败: {e}"))?;
+    fs::rename(&tmp_path, path)
+        .await
+        .map_err(|e| format!("写入缓存失败: {e}"))
+}
+
+// 测试用临时目录：每个实例一个独立目录，离开作用域时删除（断言失败 panic 时也会清理）
+#[cfg(test)]
+pub(crate) struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub(crate) fn new(label: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static SEQ: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "kg-{label}-{}-{}",
+            std::process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        Self(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trips_and_ignores_corrupt_files() {
+        let dir = TestDir::new("storage-test");
+        let path = dir.path().join("sub").join("v.json");
+
+        write_json_at(&path, &vec![1u64, 2, 3]).await.unwrap();
+        assert_eq!(read_json_at::<Vec<u64>>(&path).await, Some(vec![1, 2, 3]));
+
+        fs::write(&path, b"{not json").await.unwrap();
+        assert_eq!(read_json_at::<Vec<u64>>(&path).await, None);
+    }
+
+    #[test]
+    fn prunes_oldest_files_over_limits() {
+        let temp = TestDir::new("prune-test");
+        let dir = temp.path();
+        std::fs::create_dir_all(dir).unwrap();
+        let now = SystemTime::now();
+        for (i, name) in ["old", "mid", "new"].iter().enumerate() {
+            let path = dir.join(name);
+            std::fs::write(&path, b"0123456789").unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(100 - i as u64))
+                .unwrap();
+        }
+        prune_dir(dir, 2, u64::MAX);
+        assert!(!dir.join("old").exists());
+        assert!(dir.join("mid").exists() && dir.join("new").exists());
+
+        prune_dir(dir, usize::MAX, 15);
+        assert!(!dir.join("mid").exists() && dir.join("new").exists());
+    }
+}
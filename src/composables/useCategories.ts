@@ -1,20 +1,23 @@
 import { ref } from 'vue';
 import { invoke } from '@tauri-apps/api/core';
-import type { DropdownOption } from '@/types';
+import type { DropdownOption, DropdownOptions } from '@/types';
 
 // 分类列表全局只请求一次，Sidebar 与下载页共用
 const categories = ref<DropdownOption[]>([]);
 // 请求进行中的标记，下拉框据此显示 loading
 const loading = ref(false);
+// 分类来自本地缓存的标签树（网络不可用）
+const offline = ref(false);
 let loadPromise: Promise<DropdownOption[]> | null = null;
 
 export function useCategories() {
   const load = (): Promise<DropdownOption[]> => {
     if (!loadPromise) {
       loading.value = true;
-      loadPromise = invoke<DropdownOption[]>('fetch_textbook_categories')
-        .then((list) => {
-          categories.value = list ?? [];
+      loadPromise = invoke<DropdownOptions>('fetch_textbook_categories')
+        .then((result) => {
+          categories.value = result.options ?? [];
+          offline.value = result.offline;
           return categories.value;
         })
         .catch((error) => {
@@ -28,5 +31,5 @@ export function useCategories() {
     return loadPromise;
   };
 
-  return { categories, loading, load };
+  return { categories, loading, offline, load };
 }
@@ -1,44 +1,36 @@
 import { onMounted, ref } from 'vue';
-import { invoke } from '@tauri-apps/api/core';
+import { convertFileSrc } from '@tauri-apps/api/core';
 
-// 封面经后端代理获取（优先详情里与源 PDF 同批的转码图），
-// 按书籍 id 做内存缓存，避免翻页/返回时重复请求
-const cache = new Map<string, Promise<string>>();
-const MAX_CACHE_ENTRIES = 300;
-
-function fetchCover(bookId: string, fallbackUrl: string): Promise<string> {
-  const cached = cache.get(bookId);
-  if (cached) return cached;
-
-  const promise = invoke<string>('fetch_cover', { bookId, fallbackUrl })
-    .then((base64) => `data:image/jpeg;base64,${base64}`)
-    .catch((error) => {
-      cache.delete(bookId); // 失败不缓存，允许重试
-      throw error;
-    });
+// 封面走后端的 kgimg 协议（优先详情里与源 PDF 同批的转码图，后端落盘缓存），
+// webview 按地址直接加载，不再经 IPC 传 base64
+export function coverImageUrl(bookId: string): string {
+  return convertFileSrc(`cover/${bookId}`, 'kgimg');
+}
 
-  cache.set(bookId, promise);
-  if (cache.size > MAX_CACHE_ENTRIES) {
-    const oldest = cache.keys().next().value;
-    if (oldest) cache.delete(oldest);
-  }
-  return promise;
+// 任意公开图片（课程封面等）同样经 kgimg 协议代理
+export function proxiedImageUrl(url: string): string {
+  return convertFileSrc(`image/${url}`, 'kgimg');
 }
 
-export function useCoverImage(bookId: string, fallbackUrl: string) {
+// 先在内存里预加载，加载完成再交给 el-image，卡片可显示加载中 / 失败占位
+export function useCoverImage(bookId: string) {
   const src = ref('');
   const loading = ref(true);
   const failed = ref(false);
 
-  onMounted(async () => {
-    try {
-      src.value = await fetchCover(bookId, fallbackUrl);
-    } catch (error) {
-      console.error('获取封面失败:', error);
+  onMounted(() => {
+    const url = coverImageUrl(bookId);
+    const img = new Image();
+    img.onload = () => {
+      src.value = url;
+      loading.value = false;
+    };
+    img.onerror = () => {
+      console.error('获取封面失败:', bookId);
       failed.value = true;
-    } finally {
       loading.value = false;
-    }
+    };
+    img.src = url;
   });
 
   return { src, loading, failed };
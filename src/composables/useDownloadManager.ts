@@ -3,6 +3,7 @@ import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import { invoke } from '@tauri-apps/api/core';
 import { ElMessage, ElMessageBox } from 'element-plus';
 import { readDownloadSettings } from '@/utils/settings';
+import type { CourseParseResult, CourseResource } from '@/types';
 
 // ---------------------------------------------------------------------------
 // 全局下载池：所有下载入口只负责 enqueue，真正的启动由池按并发上限调度。
@@ -29,12 +30,19 @@ export type DownloadStatus =
 export interface TextbookDownloadPayload {
   url: string;
   title: string;
-  category_label: string;
-  subject_label: string;
-  version_label: string;
-  grade_label: string;
-  year_label: string;
+  // 分类名称链（Textbook.labels）
+  labels?: string[];
+  // 「按分类保存」的相对目录（Textbook.save_path）；为空时后端按 labels 拼
+  save_path?: string;
+  // 旧版队列里的各级分类名，后端在 labels 为空时兼容使用
+  category_label?: string;
+  subject_label?: string;
+  version_label?: string;
+  grade_label?: string;
+  year_label?: string;
   save_by_category: boolean;
+  // 资源 id：续传时后端据此重新解析源 PDF 地址
+  resource_id?: string;
 }
 
 export interface CourseDownloadPayload {
@@ -45,6 +53,20 @@ export interface CourseDownloadPayload {
   course_title: string | null;
   save_by_category: boolean;
   category_path: string[];
+  // 写入视频元数据（资源 id / 平台页面地址 / 封面）
+  resource_id: string;
+  source_url: string;
+  cover_url: string;
+  // 资源身份（详情接口地址 + 选中的 ti_file_flag）：签名地址过期后后端据此重新取地址续传
+  detail_url?: string;
+  ti_file_flag?: string;
+  mirror_urls?: string[];
+  // 视频时间段下载（秒），都不传则下载整段；precise_trim 需要 ffmpeg 重新编码
+  start_seconds?: number | null;
+  end_seconds?: number | null;
+  precise_trim?: boolean;
+  // 任务键（片段下载带区间，见 courseTaskKey），后端按它发进度事件、登记取消令牌
+  task_key?: string;
 }
 
 export interface DownloadTask {
@@ -111,7 +133,7 @@ const inflight = new Set<string>();
 const inflightPromises = new Map<string, Promise<void>>();
 
 // 速度采样锚点（非响应式即可）。按时间窗结算，而不是逐事件求瞬时值：
-// m3u8 是 8 路并发，切片常扎堆在同一两毫秒内完成，「一个切片 ÷ 1ms」会
+// m3u8 是多路并发，切片常扎堆在同一两毫秒内完成，「一个切片 ÷ 1ms」会
 // 算出 GB/s 级的假速度（Date.now 的分辨率就是 1ms，分母再小不下去）
 const speedSamples = new Map<string, { bytes: number; time: number }>();
 // 最近一次进度事件的时间，用于停滞时把速度归零
@@ -539,6 +561,64 @@ export function enqueueDownload(input: EnqueueInput): boolean {
   return true;
 }
 
+/** 视频时间段（秒）；起止都为空即整段 */
+export interface VideoRange {
+  start_seconds: number | null;
+  end_seconds: number | null;
+  precise_trim: boolean;
+}
+
+/** 课程资源的任务键：整段下载即 download_url，片段下载附上区间，与整段下载、其他片段各占一个任务 */
+export function courseTaskKey(resource: CourseResource, range?: VideoRange): string {
+  if (!resource.is_video || !range || (range.start_seconds === null && range.end_seconds === null)) {
+    return resource.download_url;
+  }
+  return `${resource.download_url}#t=${range.start_seconds ?? ''}-${range.end_seconds ?? ''}`;
+}
+
+/** 课程资源的后端入参（下载与预览共用，保证两边算出同一个目标路径与切片缓存目录） */
+export function coursePayload(
+  course: CourseParseResult,
+  resource: CourseResource,
+  saveByCategory: boolean,
+  range?: VideoRange,
+): CourseDownloadPayload {
+  return {
+    download_url: resource.download_url,
+    title: resource.title,
+    format: resource.format,
+    is_video: resource.is_video,
+    course_title: course.title,
+    save_by_category: saveByCategory,
+    category_path: course.category_path,
+    resource_id: resource.id,
+    source_url: course.source_url,
+    cover_url: resource.cover_url,
+    detail_url: course.detail_url,
+    ti_file_flag: resource.ti_file_flag,
+    mirror_urls: resource.mirror_urls,
+    ...(resource.is_video ? range : undefined),
+    task_key: courseTaskKey(resource, range),
+  };
+}
+
+/** 入队课程解析结果里的一个资源（课程页与同步课堂目录共用）；range 只对视频生效 */
+export function enqueueCourseResource(
+  course: CourseParseResult,
+  resource: CourseResource,
+  saveByCategory: boolean,
+  range?: VideoRange,
+): boolean {
+  const url = courseTaskKey(resource, range);
+  return enqueueDownload({
+    url,
+    kind: resource.is_video ? 'course-video' : 'course-doc',
+    title: url === resource.download_url ? resource.title : `${resource.title}（片段）`,
+    subtitle: course.title,
+    payload: coursePayload(course, resource, saveByCategory, range),
+  });
+}
+
 /** 暂停：排队任务原地挂起；下载中任务停止但保留半成品，继续时自动续传 */
 export function pauseDownload(url: string): void {
   const task = tasks.get(url);
@@ -597,16 +677,20 @@ export async function removeDownload(url: string, removeArtifacts = true): Promi
 
   const filePath = task.filePath;
   const finished = task.status === 'completed';
+  const resource = task.kind === 'textbook' ? null : task.payload;
   tasks.delete(url);
   speedSamples.delete(url);
   lastProgressAt.delete(url);
   scheduleSave(true);
   pump();
 
-  if (removeArtifacts && !finished && filePath) {
-    await invoke('remove_download_artifacts', { filePath }).catch((err) =>
-      console.warn('清理半成品失败:', err)
-    );
+  // 没收到目标路径的课程任务，由后端按资源信息推算半成品位置
+  if (removeArtifacts && !finished && (filePath || resource)) {
+    await invoke('remove_download_artifacts', {
+      filePath,
+      resource,
+      downloadPath: readDownloadSettings().downloadPath,
+    }).catch((err) => console.warn('清理半成品失败:', err));
   }
 }
 
@@ -1,44 +1,40 @@
-import { ref, watch, type Ref } from 'vue';
+import { ref, computed, watch, type Ref } from 'vue';
 import { invoke } from '@tauri-apps/api/core';
 import { ElMessage } from 'element-plus';
-import type { DropdownOption } from '@/types';
+import type { TagChildren, TagNode } from '@/types';
 
 // 数据源里混入的异常节点，不展示
 const EXCLUDED_OPTION = { value: '267a11ad-0a45-4d3e-a95b-423fc3e959af', label: '培智学校' };
 
-// 分类 -> 学科 -> 版本 -> 年级 -> 年份 的级联筛选：
-// 上级变化时重置下级并拉取直接下级的选项（选项保持后端返回的原始顺序）
-export function useTextbookFilters(categoryId: Ref<string>, isSpecialEducation: Ref<boolean>) {
-  const subject = ref('');
-  const version = ref('');
-  const grade = ref('');
-  const year = ref('');
-
-  const subjects = ref<DropdownOption[]>([]);
-  const versions = ref<DropdownOption[]>([]);
-  const grades = ref<DropdownOption[]>([]);
-  const years = ref<DropdownOption[]>([]);
-
-  const levels = [
-    { value: subject, options: subjects },
-    { value: version, options: versions },
-    { value: grade, options: grades },
-    { value: year, options: years },
-  ];
+export interface FilterLevel {
+  value: string;
+  options: TagNode[];
+}
 
-  const resetFrom = (level: number) => {
-    for (const { value, options } of levels.slice(level)) {
-      value.value = '';
-      options.value = [];
-    }
-  };
+// 分类之下的级联筛选，层数以标签树为准（高中没有年级、特殊教育多一层年份都不用特判）：
+// 选中某一级后丢弃其下各级，再按当前路径拉取下一级；没有下一级即到了叶子，可以列书目
+export function useTextbookFilters(categoryId: Ref<string>) {
+  const levels = ref<FilterLevel[]>([]);
+  const isLeaf = ref(false);
+  // 最近一次取到的下一级来自本地缓存（网络不可用）
+  const offline = ref(false);
+  const path = computed(() =>
+    [categoryId.value, ...levels.value.map((level) => level.value)].filter(Boolean)
+  );
 
-  const fetchOptions = async (args: Record<string, string>, target: Ref<DropdownOption[]>) => {
+  // 快速切换时只采用最后一次请求的结果
+  let requestSeq = 0;
+  const loadChildren = async (prefix: string[]) => {
+    const seq = ++requestSeq;
     try {
-      const options = await invoke<DropdownOption[]>('fetch_filter_options', { args });
-      target.value = (options ?? []).filter(
+      const result = await invoke<TagChildren>('fetch_tag_children', { path: prefix });
+      if (seq !== requestSeq) return;
+      const options = result.children.filter(
         (opt) => !(opt.value === EXCLUDED_OPTION.value && opt.label === EXCLUDED_OPTION.label)
       );
+      isLeaf.value = result.is_leaf;
+      offline.value = result.offline;
+      if (options.length) levels.value.push({ value: '', options });
     } catch (error) {
       console.error('获取筛选选项失败:', error);
       ElMessage.error('获取筛选信息出错');
@@ -48,59 +44,23 @@ export function useTextbookFilters(categoryId: Ref<string>, isSpecialEducation:
   watch(
     categoryId,
     (cat) => {
-      resetFrom(0);
-      if (cat) {
-        fetchOptions({ category_id: cat }, subjects);
-      }
+      levels.value = [];
+      isLeaf.value = false;
+      if (cat) loadChildren([cat]);
     },
     { immediate: true }
   );
 
-  watch(subject, (subj) => {
-    resetFrom(1);
-    if (subj && categoryId.value) {
-      fetchOptions({ category_id: categoryId.value, subject_id: subj }, versions);
+  const select = (index: number, value: string) => {
+    levels.value.splice(index + 1);
+    levels.value[index].value = value;
+    isLeaf.value = false;
+    if (value) {
+      loadChildren(path.value);
+    } else {
+      requestSeq += 1;
     }
-  });
-
-  watch(version, (ver) => {
-    resetFrom(2);
-    if (ver && categoryId.value && subject.value) {
-      fetchOptions(
-        { category_id: categoryId.value, subject_id: subject.value, version_id: ver },
-        grades
-      );
-    }
-  });
-
-  // 仅特殊教育有第五级「年份」
-  watch(grade, (gra) => {
-    resetFrom(3);
-    if (isSpecialEducation.value && gra && categoryId.value && subject.value && version.value) {
-      fetchOptions(
-        {
-          category_id: categoryId.value,
-          subject_id: subject.value,
-          version_id: version.value,
-          grade_id: gra,
-        },
-        years
-      );
-    }
-  });
-
-  const labelOf = (options: Ref<DropdownOption[]>, value: string) =>
-    options.value.find((opt) => opt.value === value)?.label || '';
-
-  return {
-    subject,
-    version,
-    grade,
-    year,
-    subjects,
-    versions,
-    grades,
-    years,
-    labelOf,
   };
+
+  return { levels, path, isLeaf, offline, select };
 }
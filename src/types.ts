@@ -1,6 +1,30 @@
+import type { TextbookDownloadPayload } from '@/composables/useDownloadManager';
+
 export interface DropdownOption {
   value: string;
   label: string;
+  // 该选项下的教材数；书目不可用时为 null
+  count?: number | null;
+}
+
+// fetch_textbook_categories / fetch_filter_options 的返回；offline 含义同 TextbookList
+export interface DropdownOptions {
+  options: DropdownOption[];
+  offline: boolean;
+}
+
+// 通用标签导航的节点（fetch_tag_children）；is_leaf 表示已到最细一级
+export interface TagNode extends DropdownOption {
+  is_leaf: boolean;
+}
+
+// fetch_tag_children 的返回：路径上各级名称、下一级节点、路径本身是否为叶子；
+// offline 表示标签树或计数用的书目来自本地缓存
+export interface TagChildren {
+  labels: string[];
+  children: TagNode[];
+  is_leaf: boolean;
+  offline: boolean;
 }
 
 export interface Textbook {
@@ -10,15 +34,16 @@ export interface Textbook {
   total_uv: number;
   like_count: number;
   download_url: string;
+  // 分类路径上的名称（后端由 tag_paths 还原）
+  labels: string[];
+  // 「按分类保存」时的相对目录
+  save_path: string;
 }
 
-// 当前各级筛选选中项的显示名，用于「按分类保存」的目录结构
-export interface TextbookLabels {
-  category: string;
-  subject: string;
-  version: string;
-  grade: string;
-  year: string;
+// fetch_textbooks 的返回；offline 表示网络不可用，列表来自本地缓存的旧目录
+export interface TextbookList {
+  textbooks: Textbook[];
+  offline: boolean;
 }
 
 // 后端解析课程 URL 得到的单个资源（视频或课件）
@@ -29,12 +54,82 @@ export interface CourseResource {
   download_url: string;
   is_video: boolean;
   cover_url: string;
+  // 下载地址取自哪个 ti_item，续传时据此重新解析地址；直链为空
+  ti_file_flag: string;
+  // 各 CDN 镜像上的地址（含 download_url），视频切片分散到各镜像下载
+  mirror_urls: string[];
 }
 
 // 一个课程 URL 的解析结果
 export interface CourseParseResult {
   title: string;
+  // 解析所用的平台页面地址，下载视频时写入元数据
+  source_url: string;
+  // 平台详情接口地址（直链为空）
+  detail_url: string;
   // 分类目录段（学段/学科/版本/年级/册次，可为空），用于卡片展示与「按分类保存」
   category_path: string[];
   resources: CourseResource[];
 }
+
+// 同步课堂目录里的一个课时，page_url 即平台课时页（可直接交给 parse_course_url）
+export interface LessonEntry {
+  id: string;
+  title: string;
+  page_url: string;
+  detail_url: string;
+}
+
+// 教材章节树的节点（fetch_lesson_tree）；章节树外的课时挂在 id 为空的「其他课时」下
+export interface LessonNode {
+  id: string;
+  title: string;
+  lessons: LessonEntry[];
+  children: LessonNode[];
+}
+
+// 教材的配套资源（fetch_companion_resources）：教材详情里的关联资源、配套音频、同步课堂课时各成一组
+export interface TextbookCompanions {
+  book_id: string;
+  title: string;
+  category_path: string[];
+  groups: CourseParseResult[];
+  // 没能取到的分组（网络或数据异常），与「没有这类资源」区分
+  warnings: string[];
+}
+
+// 书目版本更替时的变化（catalog-updated 事件与 fetch_catalog_changes 的返回）
+export interface BookChange {
+  id: string;
+  title: string;
+  previous_title: string | null;
+  tag_paths: string[];
+  previous_tag_paths: string[];
+}
+
+export interface CatalogChanges {
+  old_version: number;
+  new_version: number;
+  detected_at: number;
+  added: BookChange[];
+  removed: BookChange[];
+  retitled: BookChange[];
+  moved: BookChange[];
+}
+
+// 教材源文件版本：源 PDF 地址与最新转码时间戳
+export interface SourceVersion {
+  source_url: string;
+  transcode_timestamp: number;
+}
+
+// check_outdated_textbooks 的返回：平台上已有新版本的本地教材
+export interface OutdatedTextbook {
+  resource_id: string;
+  title: string;
+  file_path: string;
+  downloaded: SourceVersion;
+  current: SourceVersion;
+  // 原始下载请求，重新下载时原样入队
+  info: TextbookDownloadPayload;
+}